@@ -0,0 +1,324 @@
+use crate::{
+    content_disposition, error::AppError, serveignore::Rules, transfer_permit, ActiveStream,
+    AppState,
+};
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
+use futures::StreamExt;
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use zip::{write::SimpleFileOptions, CompressionMethod, DateTime, ZipWriter};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHANNEL_CAPACITY: usize = 4; // 打包线程最多领先发送4块
+
+// `?download=` 支持的打包格式
+#[derive(Clone, Copy)]
+pub enum Format {
+    Zip,
+    TarGz,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "zip" => Some(Format::Zip),
+            "tar.gz" => Some(Format::TarGz),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarGz => "tar.gz",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Zip => "application/zip",
+            Format::TarGz => "application/gzip",
+        }
+    }
+}
+
+// `?level=` 对应的压缩方式
+// 不指定时ZIP只打包不压缩，速度最快；tar.gz使用gzip的默认压缩级别
+#[derive(Clone, Copy)]
+enum Level {
+    Default,
+    Store,
+    Fast,
+    Best,
+}
+
+impl Level {
+    fn parse(value: Option<&str>) -> Option<Self> {
+        match value {
+            None => Some(Level::Default),
+            Some("store") => Some(Level::Store),
+            Some("fast") => Some(Level::Fast),
+            Some("best") => Some(Level::Best),
+            Some(_) => None,
+        }
+    }
+
+    fn zip_options(self) -> SimpleFileOptions {
+        let options = SimpleFileOptions::default();
+        match self {
+            Level::Default | Level::Store => options.compression_method(CompressionMethod::Stored),
+            Level::Fast => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(1)),
+            Level::Best => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(9)),
+        }
+    }
+
+    fn gzip_compression(self) -> Compression {
+        match self {
+            Level::Default => Compression::default(),
+            Level::Store => Compression::none(),
+            Level::Fast => Compression::fast(),
+            Level::Best => Compression::best(),
+        }
+    }
+}
+
+// 把压缩包写入的数据按块送进channel，作为响应体发出
+// 客户端断开后channel关闭，写入失败，打包随之停止
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// `?download=zip` 或 `?download=tar.gz` 把整个目录边遍历边打包发送
+// 不预先遍历统计总大小，响应不带Content-Length
+// 压缩包是现场生成的，两次生成的字节不保证相同，所以忽略请求中的Range，总是返回完整的200，
+// 并用 `Accept-Ranges: none` 明确告诉下载工具不能续传，否则拼接出的压缩包会损坏
+pub async fn archive_response(
+    root: PathBuf,
+    dir: PathBuf,
+    format: Format,
+    level: Option<&str>,
+    state: &AppState,
+    head_only: bool,
+) -> Result<Response, AppError> {
+    let level = Level::parse(level).ok_or_else(|| {
+        warn!("Invalid archive level: {}", level.unwrap_or_default());
+        AppError::BadRequest
+    })?;
+    let name = dir
+        .file_name()
+        .unwrap_or_else(|| "download".as_ref())
+        .to_string_lossy();
+    let name = format!("{}.{}", name, format.extension());
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        ),
+        (
+            header::CONTENT_DISPOSITION,
+            content_disposition("attachment", &name),
+        ),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        (header::ACCEPT_RANGES, HeaderValue::from_static("none")),
+    ];
+    if head_only {
+        return Ok(headers.into_response());
+    }
+    let permit = transfer_permit(state, &dir)?;
+
+    info!(
+        "Streaming {} of directory: {}",
+        format.extension(),
+        dir.display()
+    );
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let show_hidden = state.show_hidden;
+    // 读文件和压缩都是阻塞操作，放到blocking线程池里执行
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter { tx: tx.clone() });
+        let ignore = Rules::for_dir(&root, &dir);
+        let result = match format {
+            Format::Zip => write_zip(writer, &dir, level, show_hidden, ignore),
+            Format::TarGz => write_tar_gz(writer, &dir, level, show_hidden, ignore),
+        };
+        if let Err(e) = result {
+            debug!("Archive stream of {} aborted: {}", dir.display(), e);
+            // 让响应体以错误结束，客户端不会把截断的文件当成完整的压缩包
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+
+    let active = ActiveStream::new(&state.active_streams);
+    let metrics = state.metrics.clone();
+    let stream = futures::stream::poll_fn(move |cx| rx.poll_recv(cx)).map(move |chunk| {
+        let _ = (&permit, &active);
+        if let Ok(bytes) = &chunk {
+            metrics.record_bytes(bytes.len());
+        }
+        chunk
+    });
+    if state.rate_limit == 0 || state.client_buckets.is_some() {
+        return Ok((headers, axum::body::Body::from_stream(stream)).into_response());
+    }
+    let stream = crate::rate_limiter::RateLimitedStream::new(stream, state.rate_limit);
+    let mut response = (headers, axum::body::Body::from_stream(stream)).into_response();
+    crate::rate_limiter::announce(response.headers_mut(), state.rate_limit);
+    Ok(response)
+}
+
+// 目录中的一项，name是在压缩包内的相对路径，目录不带结尾的 `/`
+struct Entry {
+    path: PathBuf,
+    name: String,
+    metadata: Metadata,
+}
+
+// 与搜索一样不跟随符号链接，既不会出现循环，也不会打包挂载目录以外的文件
+// 只访问目录和普通文件，目录先于其中的内容访问；`.serveignore` 忽略的条目不打包
+fn walk(
+    root: &Path,
+    show_hidden: bool,
+    ignore: Rules,
+    mut visit: impl FnMut(&Entry) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut pending = vec![(root.to_path_buf(), String::new(), ignore)];
+    while let Some((dir, prefix, ignore)) = pending.pop() {
+        // 按名称排序，同一目录每次打包出的顺序相同
+        let mut entries: Vec<_> = fs::read_dir(&dir)?.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            if file_type.is_symlink() || (!show_hidden && name.starts_with('.')) {
+                continue;
+            }
+            if !file_type.is_dir() && !file_type.is_file() {
+                continue;
+            }
+            if ignore.is_ignored(&entry.path(), file_type.is_dir()) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let entry = Entry {
+                path: entry.path(),
+                name: format!("{}{}", prefix, name),
+                metadata,
+            };
+            visit(&entry)?;
+            if file_type.is_dir() {
+                let ignore = ignore.child(&entry.path);
+                pending.push((entry.path, format!("{}/", entry.name), ignore));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 打开失败（比如没有读权限）时跳过该文件，不中断整个压缩包
+fn open_entry(entry: &Entry) -> Option<File> {
+    File::open(&entry.path)
+        .inspect_err(|_| {
+            warn!(
+                "Skipping unreadable file in archive: {}",
+                entry.path.display()
+            )
+        })
+        .ok()
+}
+
+fn write_zip<W: Write>(
+    writer: W,
+    root: &Path,
+    level: Level,
+    show_hidden: bool,
+    ignore: Rules,
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+    walk(root, show_hidden, ignore, |entry| {
+        let options = level.zip_options().last_modified_time(zip_date(
+            entry.metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        ));
+        if entry.metadata.is_dir() {
+            // 空目录也要保留
+            zip.add_directory(entry.name.as_str(), options)?;
+        } else if let Some(mut file) = open_entry(entry) {
+            // 流式写入时无法回头改写头部，超过4GB的文件要提前声明ZIP64
+            let options = options.large_file(entry.metadata.len() >= u32::MAX as u64);
+            zip.start_file(entry.name.as_str(), options)?;
+            io::copy(&mut file, &mut zip)?;
+        }
+        Ok(())
+    })?;
+    zip.finish()?.into_inner().flush()
+}
+
+// tar头部按文件的元数据填写，保留权限位和修改时间；过长的路径使用GNU扩展
+fn write_tar_gz<W: Write>(
+    writer: W,
+    root: &Path,
+    level: Level,
+    show_hidden: bool,
+    ignore: Rules,
+) -> io::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(writer, level.gzip_compression()));
+    walk(root, show_hidden, ignore, |entry| {
+        if entry.metadata.is_dir() {
+            // 与ZIP一样，目录项以 `/` 结尾
+            tar.append_dir(format!("{}/", entry.name), &entry.path)
+        } else if let Some(mut file) = open_entry(entry) {
+            tar.append_file(&entry.name, &mut file)
+        } else {
+            Ok(())
+        }
+    })?;
+    tar.into_inner()?.finish()?.flush()
+}
+
+// ZIP里的时间是不带时区的本地时间，精度2秒，1980年以前的时间取默认值
+fn zip_date(modified: SystemTime) -> DateTime {
+    let time = time::OffsetDateTime::from(modified);
+    let time = time::UtcOffset::current_local_offset()
+        .map(|offset| time.to_offset(offset))
+        .unwrap_or(time);
+    DateTime::from_date_and_time(
+        time.year().clamp(0, u16::MAX as i32) as u16,
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+    )
+    .unwrap_or_default()
+}
@@ -0,0 +1,66 @@
+use crate::{AppState, BUILTIN_PREFIX};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::warn;
+
+const REALM: &str = "http-file-server";
+
+// clap参数校验，要求 `user:password` 格式
+pub fn parse_credential(value: &str) -> Result<String, String> {
+    match value.split_once(':') {
+        Some((user, _)) if !user.is_empty() => Ok(value.to_string()),
+        _ => Err("expected `user:password`".to_string()),
+    }
+}
+
+pub async fn basic_auth(
+    State(state): State<AppState>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    // 负载均衡器的健康检查不带认证信息
+    if state.credentials.is_empty()
+        || request.uri().path().strip_prefix(BUILTIN_PREFIX) == Some("/healthz")
+        || is_authorized(request.headers(), &state.credentials)
+    {
+        return next.run(request).await;
+    }
+
+    warn!("Unauthorized request: {}", request.uri().path());
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM),
+        )],
+    )
+        .into_response()
+}
+
+fn is_authorized(headers: &HeaderMap, credentials: &[String]) -> bool {
+    let Some(decoded) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+        .and_then(|v| STANDARD.decode(v.trim()).ok())
+    else {
+        return false;
+    };
+    // 遍历所有用户不提前返回，避免通过响应时间推断出匹配的用户
+    credentials.iter().fold(false, |ok, expected| {
+        ok | constant_time_eq(&decoded, expected.as_bytes())
+    })
+}
+
+// 长度不同直接返回，内容比较的耗时与第一个不同字节的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
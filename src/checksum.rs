@@ -0,0 +1,91 @@
+use crate::{
+    error::{AppError, PathContext as _},
+    AppState,
+};
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use moka::future::Cache;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tracing::{info, warn};
+
+const CHECKSUM_CACHE_ENTRIES: u64 = 10_000;
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+// 键中带上修改时间，文件更新后自然不会命中旧的结果
+pub type ChecksumCache = Cache<(PathBuf, SystemTime), Arc<str>>;
+
+pub fn new_cache() -> ChecksumCache {
+    Cache::builder()
+        .max_capacity(CHECKSUM_CACHE_ENTRIES)
+        .build()
+}
+
+// `?checksum=sha256` 返回文件内容的十六进制SHA-256摘要
+pub async fn checksum_response(
+    file_path: &Path,
+    algorithm: &str,
+    state: &AppState,
+) -> Result<Response, AppError> {
+    let digest = digest(file_path, algorithm, state).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        format!("{}\n", digest),
+    )
+        .into_response())
+}
+
+// 查询缓存，没有时在后台计算；目前只支持sha256
+pub async fn digest(
+    file_path: &Path,
+    algorithm: &str,
+    state: &AppState,
+) -> Result<Arc<str>, AppError> {
+    if !algorithm.eq_ignore_ascii_case("sha256") {
+        warn!("Unsupported checksum algorithm: {}", algorithm);
+        return Err(AppError::BadRequest);
+    }
+    let modified = tokio::fs::metadata(file_path)
+        .await
+        .and_then(|m| m.modified())
+        .at(file_path)?;
+
+    let key = (file_path.to_path_buf(), modified);
+    if let Some(digest) = state.checksum_cache.get(&key).await {
+        return Ok(digest);
+    }
+    info!("Computing SHA-256: {}", file_path.display());
+    let path = file_path.to_path_buf();
+    // 大文件要读很久，放到blocking线程池里执行，不占用处理请求的线程
+    let digest: Arc<str> = tokio::task::spawn_blocking(move || sha256_file(&path))
+        .await?
+        .at(file_path)?
+        .into();
+    state.checksum_cache.insert(key, digest.clone()).await;
+    Ok(digest)
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,278 @@
+use crate::{
+    auth, ip_filter, log::LogFormat, mount, parse_base_path, parse_bind, parse_cors_origin,
+    parse_mime_override, parse_size, rate_limiter::RateTiers, templates::Theme, Args,
+};
+use anyhow::{anyhow, bail, Context};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::PathBuf};
+
+// 配置文件中的键与命令行参数同名（下划线代替连字符），都是可选的
+// 大小相关的值与命令行一样写成字符串，如 "100M"
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    port: Option<u16>,
+    bind: Option<String>,
+    dual_stack: Option<bool>,
+    tcp_nodelay: Option<bool>,
+    keepalive: Option<u64>,
+    base_path: Option<String>,
+    unix: Option<PathBuf>,
+    open: Option<bool>,
+    directory: Option<PathBuf>,
+    mount: Option<Vec<String>>,
+    read_only: Option<bool>,
+    index_file: Option<String>,
+    no_index: Option<bool>,
+    no_directory_listing: Option<bool>,
+    rate_limit: Option<String>,
+    rate_limit_per_ip: Option<bool>,
+    global_rate: Option<String>,
+    tiered_rate: Option<String>,
+    auth: Option<Vec<String>>,
+    allow_ip: Option<Vec<String>>,
+    deny_ip: Option<Vec<String>>,
+    trust_proxy: Option<Vec<String>>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    max_cache_size: Option<String>,
+    cache_max_file_size: Option<String>,
+    cache_ttl: Option<u64>,
+    cache_after: Option<u32>,
+    no_cache: Option<bool>,
+    mime_override: Option<Vec<String>>,
+    sniff_mime: Option<bool>,
+    cors_origin: Option<Vec<String>>,
+    server_header: Option<bool>,
+    cache_control_max_age: Option<u64>,
+    theme: Option<Theme>,
+    title: Option<String>,
+    subtitle: Option<String>,
+    cdn: Option<bool>,
+    error_pages: Option<bool>,
+    watch: Option<bool>,
+    show_hidden: Option<bool>,
+    strict_ignore: Option<bool>,
+    sort_dirs_first: Option<bool>,
+    follow_symlinks: Option<bool>,
+    search_depth: Option<usize>,
+    max_path_depth: Option<usize>,
+    max_listing_entries: Option<usize>,
+    max_upload_size: Option<String>,
+    soft_delete: Option<bool>,
+    max_connections: Option<usize>,
+    request_timeout: Option<u64>,
+    access_log: Option<PathBuf>,
+    stats_file: Option<PathBuf>,
+    quiet: Option<bool>,
+    verbose: Option<u8>,
+    log_format: Option<LogFormat>,
+}
+
+// 解析命令行，指定了 `--config` 时再合并配置文件，命令行上显式给出的参数优先
+pub fn load_args() -> anyhow::Result<Args> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let mut from_config = HashSet::new();
+    if let Some(path) = args.config.clone() {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config: Config = toml::from_str(&content)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        config
+            .apply(&mut args, &matches, &mut from_config)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+    }
+    let given = |id: &str| {
+        from_config.contains(id) || matches.value_source(id) == Some(ValueSource::CommandLine)
+    };
+    validate(&args, given)?;
+    Ok(args)
+}
+
+// clap的requires/conflicts_with/range只检查命令行，这里按同样的规则检查合并后的结果
+// 命令行和配置文件都经过这里，新增约束时两边一起生效
+fn validate(args: &Args, given: impl Fn(&str) -> bool) -> anyhow::Result<()> {
+    if args.tls_cert.is_some() != args.tls_key.is_some() {
+        bail!("tls_cert and tls_key must be set together");
+    }
+    if args.dual_stack && given("bind") {
+        bail!("dual_stack cannot be used together with bind");
+    }
+    if args.unix.is_some() {
+        let tcp_only = [
+            ("port", given("port")),
+            ("bind", given("bind")),
+            ("dual_stack", args.dual_stack),
+            ("tcp_nodelay", given("tcp_nodelay")),
+            ("keepalive", given("keepalive")),
+            ("tls_cert", args.tls_cert.is_some()),
+            ("allow_ip", !args.allow_ips.is_empty()),
+            ("deny_ip", !args.deny_ips.is_empty()),
+            ("open", args.open),
+        ];
+        if let Some((name, _)) = tcp_only.iter().find(|(_, set)| *set) {
+            bail!("unix cannot be used together with {}", name);
+        }
+    }
+    if args.no_directory_listing && args.no_index {
+        bail!("no_directory_listing and no_index cannot be used together");
+    }
+    if args.cache_after < 1 {
+        bail!("cache_after must be at least 1");
+    }
+    if args.quiet && args.verbose > 0 {
+        bail!("quiet and verbose cannot be used together");
+    }
+    Ok(())
+}
+
+// 配置文件里出现的键记进given，合并后和命令行上给出的参数一起做冲突检查
+macro_rules! merge {
+    ($args:expr, $matches:expr, $given:expr, $field:ident, $value:expr) => {
+        if let Some(value) = $value {
+            $given.insert(stringify!($field));
+            if $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine) {
+                $args.$field = value;
+            }
+        }
+    };
+}
+
+impl Config {
+    fn apply(
+        self,
+        args: &mut Args,
+        matches: &ArgMatches,
+        given: &mut HashSet<&'static str>,
+    ) -> anyhow::Result<()> {
+        let mounts = parse_each("mount", self.mount, mount::parse_mount)?;
+        let credentials = parse_each("auth", self.auth, auth::parse_credential)?;
+        let allow_ips = parse_each("allow_ip", self.allow_ip, ip_filter::parse_ip_net)?;
+        let deny_ips = parse_each("deny_ip", self.deny_ip, ip_filter::parse_ip_net)?;
+        let trusted_proxies = parse_each("trust_proxy", self.trust_proxy, ip_filter::parse_ip_net)?;
+        let mime_overrides = parse_each("mime_override", self.mime_override, parse_mime_override)?;
+        let cors_origins = parse_each("cors_origin", self.cors_origin, parse_cors_origin)?;
+        let bind = parse_one("bind", self.bind, parse_bind)?;
+        let base_path = parse_one("base_path", self.base_path, parse_base_path)?;
+        let rate_limit = parse_one("rate_limit", self.rate_limit, parse_size)?;
+        let global_rate = parse_one("global_rate", self.global_rate, parse_size)?;
+        let tiered_rate = parse_one("tiered_rate", self.tiered_rate, RateTiers::parse)?;
+        let max_cache_size = parse_one("max_cache_size", self.max_cache_size, parse_size)?;
+        let cache_max_file_size =
+            parse_one("cache_max_file_size", self.cache_max_file_size, parse_size)?;
+        let max_upload_size = parse_one("max_upload_size", self.max_upload_size, parse_size)?;
+
+        merge!(args, matches, given, port, self.port);
+        merge!(args, matches, given, bind, bind);
+        merge!(args, matches, given, dual_stack, self.dual_stack);
+        merge!(args, matches, given, tcp_nodelay, self.tcp_nodelay);
+        merge!(args, matches, given, keepalive, self.keepalive);
+        merge!(args, matches, given, base_path, base_path);
+        merge!(args, matches, given, unix, self.unix.map(Some));
+        merge!(args, matches, given, open, self.open);
+        merge!(args, matches, given, directory, self.directory.map(Some));
+        merge!(args, matches, given, mounts, mounts);
+        merge!(args, matches, given, read_only, self.read_only);
+        merge!(args, matches, given, index_file, self.index_file);
+        merge!(args, matches, given, no_index, self.no_index);
+        merge!(
+            args,
+            matches,
+            given,
+            no_directory_listing,
+            self.no_directory_listing
+        );
+        merge!(args, matches, given, rate_limit, rate_limit);
+        merge!(
+            args,
+            matches,
+            given,
+            rate_limit_per_ip,
+            self.rate_limit_per_ip
+        );
+        merge!(args, matches, given, global_rate, global_rate);
+        merge!(args, matches, given, tiered_rate, tiered_rate.map(Some));
+        merge!(args, matches, given, credentials, credentials);
+        merge!(args, matches, given, allow_ips, allow_ips);
+        merge!(args, matches, given, deny_ips, deny_ips);
+        merge!(args, matches, given, trusted_proxies, trusted_proxies);
+        merge!(args, matches, given, tls_cert, self.tls_cert.map(Some));
+        merge!(args, matches, given, tls_key, self.tls_key.map(Some));
+        merge!(args, matches, given, max_cache_size, max_cache_size);
+        merge!(
+            args,
+            matches,
+            given,
+            cache_max_file_size,
+            cache_max_file_size
+        );
+        merge!(args, matches, given, cache_ttl, self.cache_ttl);
+        merge!(args, matches, given, cache_after, self.cache_after);
+        merge!(args, matches, given, no_cache, self.no_cache);
+        merge!(args, matches, given, mime_overrides, mime_overrides);
+        merge!(args, matches, given, sniff_mime, self.sniff_mime);
+        merge!(args, matches, given, cors_origins, cors_origins);
+        merge!(args, matches, given, server_header, self.server_header);
+        merge!(
+            args,
+            matches,
+            given,
+            cache_control_max_age,
+            self.cache_control_max_age
+        );
+        merge!(args, matches, given, theme, self.theme);
+        merge!(args, matches, given, title, self.title.map(Some));
+        merge!(args, matches, given, subtitle, self.subtitle.map(Some));
+        merge!(args, matches, given, cdn, self.cdn);
+        merge!(args, matches, given, error_pages, self.error_pages);
+        merge!(args, matches, given, watch, self.watch);
+        merge!(args, matches, given, show_hidden, self.show_hidden);
+        merge!(args, matches, given, strict_ignore, self.strict_ignore);
+        merge!(args, matches, given, sort_dirs_first, self.sort_dirs_first);
+        merge!(args, matches, given, follow_symlinks, self.follow_symlinks);
+        merge!(args, matches, given, search_depth, self.search_depth);
+        merge!(args, matches, given, max_path_depth, self.max_path_depth);
+        merge!(
+            args,
+            matches,
+            given,
+            max_listing_entries,
+            self.max_listing_entries
+        );
+        merge!(args, matches, given, max_upload_size, max_upload_size);
+        merge!(args, matches, given, soft_delete, self.soft_delete);
+        merge!(args, matches, given, max_connections, self.max_connections);
+        merge!(args, matches, given, request_timeout, self.request_timeout);
+        merge!(args, matches, given, access_log, self.access_log.map(Some));
+        merge!(args, matches, given, stats_file, self.stats_file.map(Some));
+        merge!(args, matches, given, quiet, self.quiet);
+        merge!(args, matches, given, verbose, self.verbose);
+        merge!(args, matches, given, log_format, self.log_format);
+        Ok(())
+    }
+}
+
+// 复用命令行参数的value_parser，报错时带上键名
+fn parse_one<T>(
+    key: &str,
+    value: Option<String>,
+    parser: fn(&str) -> Result<T, String>,
+) -> anyhow::Result<Option<T>> {
+    value
+        .map(|v| parser(&v))
+        .transpose()
+        .map_err(|e| anyhow!("{}: {}", key, e))
+}
+
+fn parse_each<T>(
+    key: &str,
+    values: Option<Vec<String>>,
+    parser: fn(&str) -> Result<T, String>,
+) -> anyhow::Result<Option<Vec<T>>> {
+    values
+        .map(|values| values.iter().map(|v| parser(v)).collect())
+        .transpose()
+        .map_err(|e: String| anyhow!("{}: {}", key, e))
+}
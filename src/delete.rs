@@ -0,0 +1,125 @@
+use crate::{
+    confine_path,
+    error::{AppError, PathContext as _},
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use std::{
+    io,
+    path::{Component, Path as StdPath, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+use tracing::{info, warn};
+
+// `--soft-delete` 时删除的文件移到挂载目录下的这个隐藏目录，保留原来的相对路径
+const TRASH_DIR: &str = ".trash";
+
+// 删除文件或空目录；非空目录返回409，不做递归删除
+pub async fn handle_delete(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let decoded_path = percent_decode_str(&path).decode_utf8().map_err(|_| {
+        warn!("Invalid UTF-8 in path: {}", path);
+        AppError::BadRequest
+    })?;
+    let Some((mount, relative)) = state.resolve(&decoded_path) else {
+        warn!("No mount for delete path: {}", decoded_path);
+        return Err(AppError::NotFound);
+    };
+
+    let mut relative_path = PathBuf::new();
+    for component in StdPath::new(relative).components() {
+        match component {
+            Component::Normal(part) => relative_path.push(part),
+            Component::CurDir => {}
+            _ => {
+                warn!("Directory traversal attempt blocked: {}", decoded_path);
+                return Err(AppError::Forbidden);
+            }
+        }
+    }
+    // 挂载目录本身不能删除
+    let Some(file_name) = relative_path.file_name().map(|n| n.to_owned()) else {
+        warn!("Refusing to delete mount root: {}", decoded_path);
+        return Err(AppError::Forbidden);
+    };
+
+    // 只对父目录做canonicalize，符号链接删除的是链接本身而不是它指向的文件
+    let parent = mount
+        .root
+        .join(relative_path.parent().unwrap_or(StdPath::new("")));
+    let target = confine_path(&mount.root, &parent, state.follow_symlinks)
+        .await?
+        .join(file_name);
+    let metadata = fs::symlink_metadata(&target).await.at(&target)?;
+    if metadata.is_symlink() && !state.follow_symlinks {
+        warn!("Refusing to follow symlink: {}", decoded_path);
+        return Err(AppError::Forbidden);
+    }
+    if metadata.is_dir() && !is_empty_dir(&target).await? {
+        warn!(
+            "Refusing to delete non-empty directory: {}",
+            target.display()
+        );
+        return Err(AppError::Conflict);
+    }
+
+    // 回收站里的条目再删除一次就真正删掉
+    let trash = mount.root.join(TRASH_DIR);
+    if state.soft_delete && !target.starts_with(&trash) {
+        let destination = trash_destination(&trash.join(&relative_path)).await;
+        if let Some(dir) = destination.parent() {
+            fs::create_dir_all(dir).await.at(dir)?;
+        }
+        fs::rename(&target, &destination).await.at(&target)?;
+        info!(
+            "Moved to trash: {} -> {}",
+            target.display(),
+            destination.display()
+        );
+    } else {
+        if metadata.is_dir() {
+            fs::remove_dir(&target).await
+        } else {
+            fs::remove_file(&target).await
+        }
+        .map_err(|source| match source.kind() {
+            // 检查之后目录里又有了新文件
+            io::ErrorKind::DirectoryNotEmpty => AppError::Conflict,
+            _ => AppError::Io {
+                source,
+                path: Some(target.clone()),
+            },
+        })?;
+        info!("Deleted: {}", target.display());
+    }
+    state.file_cache.invalidate(&target).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn is_empty_dir(path: &StdPath) -> Result<bool, AppError> {
+    let mut entries = fs::read_dir(path).await.at(path)?;
+    Ok(entries.next_entry().await.at(path)?.is_none())
+}
+
+// 回收站里已经有同名条目时加上时间戳，不覆盖之前删除的文件
+async fn trash_destination(path: &StdPath) -> PathBuf {
+    if fs::symlink_metadata(path).await.is_err() {
+        return path.to_path_buf();
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}", name, nanos))
+}
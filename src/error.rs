@@ -0,0 +1,108 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::task::JoinError;
+use tracing::{error, warn};
+
+// 处理请求时的错误，转换成对应的状态码
+// 客户端造成的错误在返回前由调用方记录日志，I/O和后台任务的错误在这里统一记录
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    // 同时进行的传输数达到上限，客户端稍后重试
+    Unavailable,
+    Internal,
+    Io {
+        source: io::Error,
+        path: Option<PathBuf>,
+    },
+    Task(JoinError),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest => StatusCode::BAD_REQUEST,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Conflict => StatusCode::CONFLICT,
+            AppError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Task(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn log(&self) {
+        match self {
+            AppError::Io { source, path } => {
+                let path = path
+                    .as_deref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                if self.status().is_server_error() {
+                    error!("I/O error on {}: {}", path, source);
+                } else {
+                    warn!("Cannot access {}: {}", path, source);
+                }
+            }
+            AppError::Task(e) => error!("Background task failed: {}", e),
+            _ => {}
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(source: io::Error) -> Self {
+        AppError::Io { source, path: None }
+    }
+}
+
+impl From<JoinError> for AppError {
+    fn from(e: JoinError) -> Self {
+        AppError::Task(e)
+    }
+}
+
+// 不带响应体，需要错误页面时由调用方替换（见 `--error-pages`）
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.log();
+        match self {
+            AppError::Unavailable => (self.status(), [(header::RETRY_AFTER, "5")]).into_response(),
+            _ => self.status().into_response(),
+        }
+    }
+}
+
+// 给I/O错误带上出错的路径，日志里才能看出是哪个文件
+pub trait PathContext<T> {
+    fn at(self, path: &Path) -> Result<T, AppError>;
+}
+
+impl<T> PathContext<T> for io::Result<T> {
+    fn at(self, path: &Path) -> Result<T, AppError> {
+        self.map_err(|source| AppError::Io {
+            source,
+            path: Some(path.to_path_buf()),
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use crate::AppState;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+pub struct IpRules {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl IpRules {
+    // 拒绝规则优先；允许列表为空时不限制
+    fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    // 直连的对端是受信任的代理时才看X-Forwarded-For/X-Real-IP，否则完全忽略，防止伪造
+    // X-Forwarded-For从右往左跳过受信任的代理，第一个不受信任的地址就是客户端；
+    // 更左边的值可能是客户端自己伪造的，不能直接取最左边一项
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        // 监听IPv6地址时IPv4客户端会以 ::ffff:a.b.c.d 的形式出现
+        let peer = peer.to_canonical();
+        if !self.is_trusted_proxy(peer) {
+            return peer;
+        }
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        if !forwarded.is_empty() {
+            let mut client = peer;
+            for hop in forwarded.iter().rev() {
+                let Some(ip) = parse_hop(hop) else {
+                    break;
+                };
+                client = ip;
+                if !self.is_trusted_proxy(ip) {
+                    break;
+                }
+            }
+            return client;
+        }
+        headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_hop)
+            .unwrap_or(peer)
+    }
+}
+
+// 代理可能带上端口，如 `203.0.113.7:41234` 或 `[2001:db8::1]:80`
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim();
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+// clap参数校验，接受CIDR或单个IP地址
+pub fn parse_ip_net(value: &str) -> Result<IpNet, String> {
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid IP address or CIDR range: {}", value))
+}
+
+pub async fn ip_filter(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Response {
+    // Unix socket连接没有IP，--unix与IP规则不能同时使用
+    let Some(ConnectInfo(addr)) = connect_info else {
+        return next.run(request).await;
+    };
+    let ip = state.ip_rules.client_ip(addr.ip(), request.headers());
+    if state.ip_rules.permits(ip) {
+        return next.run(request).await;
+    }
+
+    warn!("Blocked request from {}: {}", ip, request.uri().path());
+    StatusCode::FORBIDDEN.into_response()
+}
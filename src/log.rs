@@ -1,41 +1,129 @@
+use crate::{mount::Mount, AppState, Args, BUILTIN_PREFIX};
 use axum::{
-    extract::ConnectInfo,
-    http::{Method, Uri},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, Version},
     middleware::Next,
     response::Response,
 };
 use colored::*;
-use std::{net::SocketAddr, time::Instant};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 use tracing_subscriber::{fmt, EnvFilter};
-use crate::Args;
-pub fn init() {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    fmt()
-        .with_env_filter(filter)
-        .with_ansi(true)
-        .with_target(false)
-        .without_time()
-        .init();
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+pub fn init(quiet: bool, verbose: u8, format: LogFormat) {
+    let level = match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        (false, _) => "trace",
+    };
+    // 设置了RUST_LOG时以环境变量为准
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+
+    match format {
+        LogFormat::Pretty => fmt()
+            .with_env_filter(filter)
+            .with_ansi(true)
+            .with_target(false)
+            .without_time()
+            .init(),
+        // 每条日志一行JSON，带时间戳，方便Loki/ELK等工具采集
+        LogFormat::Json => {
+            JSON_OUTPUT.store(true, Ordering::Relaxed);
+            colored::control::set_override(false);
+            fmt()
+                .json()
+                .with_env_filter(filter)
+                .with_target(false)
+                .with_current_span(false)
+                .init()
+        }
+    }
+}
+
+// 启动和退出时的提示，JSON格式下改成info日志，保证输出的每一行都是JSON
+pub fn notice(symbol: ColoredString, message: String) {
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        tracing::info!("{}", message);
+    } else {
+        println!("{} {}", symbol, message);
+    }
 }
 
+pub fn blank_line() {
+    if !JSON_OUTPUT.load(Ordering::Relaxed) {
+        println!();
+    }
+}
+
+pub type AccessLog = Arc<Mutex<File>>;
+
+static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
 pub async fn logging(
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     method: Method,
-    uri: Uri,
-    request: axum::extract::Request,
+    OriginalUri(original_uri): OriginalUri,
+    version: Version,
+    mut request: axum::extract::Request,
     next: Next,
 ) -> Response {
     let start = Instant::now();
+    // 监听Unix socket时没有对端地址
+    let client = connect_info.as_ref().map_or_else(
+        || "unix".to_string(),
+        |ConnectInfo(addr)| {
+            state
+                .ip_rules
+                .client_ip(addr.ip(), request.headers())
+                .to_string()
+        },
+    );
+    let trusted = connect_info
+        .is_some_and(|ConnectInfo(addr)| state.ip_rules.is_trusted_proxy(addr.ip().to_canonical()));
+    let request_id = request_id(request.headers(), trusted);
+    request
+        .headers_mut()
+        .insert(REQUEST_ID.clone(), request_id.clone());
+    // 设置了 `--base-path` 时request的uri已经去掉前缀，日志里记录客户端请求的原始路径
+    let uri = request.uri().clone();
     let path = uri.path();
-    let query = uri.query().unwrap_or("");
+    let query = original_uri.query().unwrap_or("");
 
-    let response = next.run(request).await;
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(REQUEST_ID.clone(), request_id.clone());
+    let request_id = request_id.to_str().unwrap_or("-");
     let status = response.status();
+    // 抓取指标的请求本身不计入，避免干扰统计
+    if path.strip_prefix(BUILTIN_PREFIX) != Some("/metrics") {
+        state.metrics.record_response(status);
+    }
     let duration = start.elapsed();
 
-    let timestamp = time::OffsetDateTime::now_local()
-        .unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+    let now = time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    let timestamp = now
         .format(
             &time::format_description::parse(
                 "[day]/[month repr:short]/[year] [hour]:[minute]:[second]",
@@ -45,11 +133,49 @@ pub async fn logging(
         .unwrap_or_else(|_| "Unknown".to_string());
 
     let full_path = if query.is_empty() {
-        path.to_string()
+        original_uri.path().to_string()
     } else {
-        format!("{}?{}", path, query)
+        format!("{}?{}", original_uri.path(), query)
     };
 
+    let content_length = response
+        .headers()
+        .get("content-length")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("-");
+
+    if let Some(access_log) = &state.access_log {
+        write_access_log(
+            access_log,
+            &client,
+            now,
+            &method,
+            &full_path,
+            version,
+            status.as_u16(),
+            content_length,
+        );
+    }
+
+    if state.quiet {
+        return response;
+    }
+
+    // JSON格式时每个请求输出一条带字段的结构化日志，不再手工拼接
+    if state.log_format == LogFormat::Json {
+        tracing::info!(
+            method = %method,
+            path = %full_path,
+            status = status.as_u16(),
+            bytes = content_length.parse::<u64>().ok(),
+            duration_ms = duration.as_millis() as u64,
+            client_ip = %client,
+            request_id,
+            "request"
+        );
+        return response;
+    }
+
     let status_colored = match status.as_u16() {
         200..=299 => status.to_string().green(),
         300..=399 => status.to_string().yellow(),
@@ -67,18 +193,15 @@ pub async fn logging(
     };
 
     println!(
-        "{} - - [{}] \"{} {} HTTP/1.1\" {} {} - {:.2}ms",
-        addr.ip().to_string().cyan(),
+        "{} - - [{}] \"{} {} HTTP/1.1\" {} {} - {:.2}ms {}",
+        client.cyan(),
         timestamp,
         method_colored,
         full_path.bright_white(),
         status_colored,
-        response
-            .headers()
-            .get("content-length")
-            .and_then(|h| h.to_str().ok())
-            .unwrap_or("-"),
-        duration.as_millis()
+        content_length,
+        duration.as_millis(),
+        request_id.bright_black()
     );
 
     // 错误状态码额外打印错误信息
@@ -97,7 +220,54 @@ pub async fn logging(
 
     response
 }
-pub fn banner(args: &Args, serve_dir: &std::path::Path) {
+// 受信任的代理传来的ID沿用，方便把代理和这里的日志对上；否则生成新的UUID
+// 只接受不太长的可见字符，避免日志里混进奇怪的内容
+fn request_id(headers: &HeaderMap, trusted: bool) -> HeaderValue {
+    headers
+        .get(&REQUEST_ID)
+        .filter(|_| trusted)
+        .filter(|value| {
+            let value = value.as_bytes();
+            !value.is_empty() && value.len() <= 128 && value.iter().all(u8::is_ascii_graphic)
+        })
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+                .expect("UUID is a valid header value")
+        })
+}
+
+// Common Log Format，不带颜色，方便其他工具解析
+#[allow(clippy::too_many_arguments)]
+fn write_access_log(
+    access_log: &AccessLog,
+    client: &str,
+    now: time::OffsetDateTime,
+    method: &Method,
+    full_path: &str,
+    version: Version,
+    status: u16,
+    content_length: &str,
+) {
+    let timestamp = now
+        .format(
+            &time::format_description::parse(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]",
+            )
+            .unwrap(),
+        )
+        .unwrap_or_else(|_| "-".to_string());
+    let line = format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}\n",
+        client, timestamp, method, full_path, version, status, content_length
+    );
+    let mut file = access_log.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        tracing::error!("Failed to write access log: {}", e);
+    }
+}
+
+pub fn banner(args: &Args, mounts: &[Mount], single_file: Option<&Path>) {
     println!();
     println!(
         "{}",
@@ -106,23 +276,75 @@ pub fn banner(args: &Args, serve_dir: &std::path::Path) {
     println!(
         "{} {}",
         "🚀 Swizzer's HTTP File Server".bright_white().bold(),
-        "v0.2.1".bright_black()
+        concat!("v", env!("CARGO_PKG_VERSION")).bright_black()
     );
     println!(
         "{}",
         "═══════════════════════════════════════════════════════".bright_blue()
     );
     println!();
+    match single_file {
+        Some(file) => println!(
+            "{:<15} / → {} {}",
+            "Serving:".bright_white(),
+            file.display().to_string().cyan(),
+            "(single file)".bright_black()
+        ),
+        None => {
+            for mount in mounts {
+                println!(
+                    "{:<15} /{} → {}",
+                    "Serving:".bright_white(),
+                    mount.prefix.cyan(),
+                    mount.root.display().to_string().cyan()
+                );
+            }
+        }
+    }
+    match &args.unix {
+        Some(path) => println!(
+            "{:<15} unix:{}",
+            "Binding:".bright_white(),
+            path.display().to_string().yellow()
+        ),
+        None => println!(
+            "{:<15} {}://{}{}",
+            "Binding:".bright_white(),
+            args.scheme().yellow(),
+            args.listen_addr().to_string().yellow(),
+            if args.dual_stack {
+                " (IPv4 + IPv6)"
+            } else {
+                ""
+            }
+        ),
+    }
+    println!(
+        "{:<15} {}",
+        "Mode:".bright_white(),
+        if args.read_only {
+            "read-only".green()
+        } else {
+            "read-write (PUT enabled)".yellow()
+        }
+    );
     println!(
         "{:<15} {}",
-        "Serving:".bright_white(),
-        serve_dir.display().to_string().cyan()
+        "Rate limit:".bright_white(),
+        if args.rate_limit == 0 {
+            "unlimited".to_string().green()
+        } else {
+            format!("{} KB/s per download", args.rate_limit / 1024).yellow()
+        }
     );
     println!(
-        "{:<15} {}:{}",
-        "Binding:".bright_white(),
-        args.bind.yellow(),
-        args.port.to_string().yellow()
+        "{:<15} {}",
+        "Auth:".bright_white(),
+        if args.credentials.is_empty() {
+            "disabled".to_string().yellow()
+        } else {
+            format!("basic ({} users)", args.credentials.len()).green()
+        }
     );
     println!(
         "{:<15} {}",
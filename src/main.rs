@@ -1,53 +1,503 @@
+use anyhow::Context as _;
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, HeaderMap, StatusCode},
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{
+        header, Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, Version,
+    },
     middleware,
-    response::{Html, IntoResponse, Response},
-    routing::get,
-    Router,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, head, post, MethodRouter},
+    Json, Router,
 };
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+    Handle,
+};
+use bytes::Bytes;
 use clap::Parser;
 use colored::*;
-use futures::Stream;
+use error::{AppError, PathContext as _};
+use futures::StreamExt;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use mime_guess::Mime;
 use moka::future::Cache;
-use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 use std::{
+    collections::HashMap,
     fs,
-    future::Future,
-    net::SocketAddr,
-    path::{Path as StdPath, PathBuf},
-    pin::Pin,
-    sync::Arc,
-    task::{Context, Poll},
+    io::SeekFrom,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::fs::FileTypeExt,
+    path::{Component, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::SystemTime,
 };
 use tokio::{
     fs::File,
-    time::{Duration, Instant, Sleep},
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Duration, Instant},
 };
 use tokio_util::io::ReaderStream;
-use tower_http::cors::CorsLayer;
-use tracing::{error, info, warn};
+use tower::ServiceExt as _;
+use tower_http::{
+    compression::{
+        predicate::{DefaultPredicate, Predicate},
+        CompressionLayer,
+    },
+    cors::{AllowOrigin, CorsLayer},
+    timeout::TimeoutLayer,
+};
+use tracing::{debug, error, info, warn};
+mod archive;
+mod auth;
+mod checksum;
+mod config;
+mod delete;
+mod error;
+mod ip_filter;
 mod log;
+mod markdown;
+mod metrics;
+mod mount;
+mod qr;
+mod rate_limiter;
+mod search;
+mod serveignore;
+mod stats;
 mod templates;
+mod thumbnail;
+mod tus;
+mod upload;
+mod watch;
 
-const CACHE_FILE_SIZE_LIMIT: u64 = 4 * 1024 * 1024; // 缓存文件大小限制4MB
-const CACHE_FILE_NUM_LIMIT: u64 = 128; // 最多缓存128个文件
-const RATE_LIMIT_BYTES_PER_SEC: usize = 100 * 1024 * 1024; // 限速100MB/s
-const CACHE_FILE_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60); // 缓存文件2小时
+const CACHE_REQUEST_COUNTERS: u64 = 10_000; // 最多记录这么多个文件的请求次数
+const CACHE_REQUEST_WINDOW: Duration = Duration::from_secs(60 * 60); // 一小时没有再被请求就重新计数
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10); // 退出时最多等待10秒
+const STREAM_BUFFER_BUDGET: usize = 64 * 1024 * 1024; // 所有流式传输缓冲区总共约64MB
+const DEFAULT_PAGE_SIZE: usize = 500; // 目录列表每页默认条目数
+const MAX_PAGE_SIZE: usize = 5000;
+const STREAM_BUFFER_MIN: usize = 16 * 1024; // 单个流的缓冲区至少16KB
+const STREAM_BUFFER_FILE_FRACTION: u64 = 32; // 缓冲区不超过文件大小的1/32
+const TEXT_VIEW_SIZE_LIMIT: u64 = 1024 * 1024; // 网页预览的文本文件最大1MB
+const SNIFF_LENGTH: usize = 8 * 1024; // 按内容识别类型时最多读取文件开头8KB
+const BUILTIN_PREFIX: &str = "/_sfs"; // 内置接口的地址前缀，服务目录中同名的路径无法访问
+const SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 #[derive(Parser)]
 #[command(name = "http-file-server")]
 #[command(about = "A simple HTTP file server similar to `python -m http.server`")]
 struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Read options from a TOML file; flags given on the command line take precedence"
+    )]
+    config: Option<PathBuf>,
+
     #[arg(short, long, default_value = "8000")]
     port: u16,
 
-    #[arg(short, long, default_value = "0.0.0.0")]
-    bind: String,
+    #[arg(
+        short,
+        long,
+        default_value = "0.0.0.0",
+        value_parser = parse_bind,
+        help = "IP address to listen on, IPv6 may be written as `::` or `[::]`"
+    )]
+    bind: IpAddr,
+
+    #[arg(
+        long,
+        conflicts_with = "bind",
+        help = "Listen on a single socket accepting both IPv4 and IPv6 clients"
+    )]
+    dual_stack: bool,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        value_parser = clap::builder::BoolishValueParser::new(),
+        help = "Set TCP_NODELAY on accepted connections so small responses are sent without delay; pass `--tcp-nodelay off` to keep Nagle's algorithm"
+    )]
+    tcp_nodelay: bool,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "0",
+        help = "Send TCP keepalive probes after a connection has been idle this long, to drop dead peers; 0 disables"
+    )]
+    keepalive: u64,
+
+    #[arg(
+        long,
+        default_value = "",
+        value_parser = parse_base_path,
+        help = "URL path prefix when served under a reverse proxy subpath, e.g. /files"
+    )]
+    base_path: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["port", "bind", "dual_stack", "tcp_nodelay", "keepalive", "tls_cert", "allow_ips", "deny_ips"],
+        help = "Listen on a Unix domain socket instead of TCP, e.g. for a local reverse proxy"
+    )]
+    unix: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "unix",
+        help = "Open the server URL in the default browser once it is listening"
+    )]
+    open: bool,
 
     #[arg(help = "Directory to serve (default: current directory)")]
     directory: Option<PathBuf>,
+
+    #[arg(
+        long = "mount",
+        value_name = "/PREFIX=PATH",
+        value_parser = mount::parse_mount,
+        help = "Serve PATH under /PREFIX; repeatable. Without a directory argument only the mounts are served"
+    )]
+    mounts: Vec<(String, PathBuf)>,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Reject uploads; pass `--read-only false` to enable PUT"
+    )]
+    read_only: bool,
+
+    #[arg(
+        long,
+        default_value = "index.html",
+        help = "File served instead of the listing when present in a directory"
+    )]
+    index_file: String,
+
+    #[arg(
+        long,
+        help = "Always show the directory listing, even if an index file exists"
+    )]
+    no_index: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "no_index",
+        help = "Never list directory contents: directories without an index file, their archives and /_sfs/search answer 403"
+    )]
+    no_directory_listing: bool,
+
+    #[arg(
+        long,
+        default_value = "100M",
+        value_parser = parse_size,
+        help = "Per-download rate limit in bytes/sec, e.g. 1M, 500K; 0 disables limiting"
+    )]
+    rate_limit: u64,
+
+    #[arg(
+        long,
+        help = "Apply --rate-limit to all downloads of a client IP together instead of to each download"
+    )]
+    rate_limit_per_ip: bool,
+
+    #[arg(
+        long,
+        default_value = "0",
+        value_parser = parse_size,
+        help = "Cap total egress of the whole server in bytes/sec, e.g. 50M; 0 disables"
+    )]
+    global_rate: u64,
+
+    #[arg(
+        long,
+        value_name = "SIZE:RATE,...",
+        value_parser = rate_limiter::RateTiers::parse,
+        help = "Per-download rate by file size, e.g. 1G:5M,100M:20M; files below every tier use --rate-limit"
+    )]
+    tiered_rate: Option<rate_limiter::RateTiers>,
+
+    #[arg(
+        long = "auth",
+        value_name = "USER:PASSWORD",
+        value_parser = auth::parse_credential,
+        help = "Require HTTP Basic authentication; repeat to add more users"
+    )]
+    credentials: Vec<String>,
+
+    #[arg(
+        long = "allow-ip",
+        value_name = "CIDR",
+        value_parser = ip_filter::parse_ip_net,
+        help = "Only accept clients in this range, e.g. 192.168.1.0/24; repeatable. Empty allows everyone"
+    )]
+    allow_ips: Vec<ipnet::IpNet>,
+
+    #[arg(
+        long = "deny-ip",
+        value_name = "CIDR",
+        value_parser = ip_filter::parse_ip_net,
+        help = "Reject clients in this range; repeatable, takes precedence over --allow-ip"
+    )]
+    deny_ips: Vec<ipnet::IpNet>,
+
+    #[arg(
+        long = "trust-proxy",
+        value_name = "CIDR",
+        value_parser = ip_filter::parse_ip_net,
+        help = "Take the client address from X-Forwarded-For/X-Real-IP when the peer is in this range; repeatable"
+    )]
+    trusted_proxies: Vec<ipnet::IpNet>,
+
+    #[arg(
+        long = "cors-origin",
+        value_name = "ORIGIN",
+        value_parser = parse_cors_origin,
+        help = "Let pages from ORIGIN (e.g. https://example.com, or * for any site) read responses via CORS; repeatable. \
+                Any allowed origin can read every file the server exposes to its visitors. Default: same-origin only"
+    )]
+    cors_origins: Vec<HeaderValue>,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        value_parser = clap::builder::BoolishValueParser::new(),
+        help = "Send a Server header with the program name and version; pass `--server-header off` to hide it"
+    )]
+    server_header: bool,
+
+    #[arg(
+        long,
+        requires = "tls_key",
+        help = "PEM certificate chain; enables HTTPS"
+    )]
+    tls_cert: Option<PathBuf>,
+
+    #[arg(long, requires = "tls_cert", help = "PEM private key for --tls-cert")]
+    tls_key: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "256M",
+        value_parser = parse_size,
+        help = "Total memory for cached small files, e.g. 64M; least recently used files are evicted once exceeded"
+    )]
+    max_cache_size: u64,
+
+    #[arg(
+        long,
+        default_value = "4M",
+        value_parser = parse_size,
+        help = "Largest file kept in the memory cache, e.g. 1M; larger files are streamed from disk"
+    )]
+    cache_max_file_size: u64,
+
+    #[arg(
+        long,
+        default_value = "7200",
+        help = "Seconds a cached file is kept before it is read from disk again; 0 keeps it until evicted"
+    )]
+    cache_ttl: u64,
+
+    #[arg(
+        long,
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Cache a small file only once it has been requested this many times; until then it is streamed from disk"
+    )]
+    cache_after: u32,
+
+    #[arg(
+        long,
+        help = "Do not cache small files in memory; every file is streamed from disk"
+    )]
+    no_cache: bool,
+
+    #[arg(
+        long = "mime-override",
+        value_name = ".EXT=TYPE",
+        value_parser = parse_mime_override,
+        help = "Content-Type for an extension, e.g. .ts=text/typescript; repeatable, case-insensitive"
+    )]
+    mime_overrides: Vec<(String, Mime)>,
+
+    #[arg(
+        long,
+        help = "Detect the Content-Type of files whose extension is unknown from their first bytes (images, archives, media...)"
+    )]
+    sniff_mime: bool,
+
+    #[arg(
+        long,
+        default_value = "60",
+        value_name = "SECONDS",
+        help = "Cache-Control max-age for files; 0 makes browsers revalidate every time. Listings are always no-cache"
+    )]
+    cache_control_max_age: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "auto",
+        help = "Initial listing theme; auto follows the browser's prefers-color-scheme"
+    )]
+    theme: templates::Theme,
+
+    #[arg(
+        long,
+        help = "Title shown in the page header and browser tab instead of the default branding"
+    )]
+    title: Option<String>,
+
+    #[arg(long, help = "Line of text shown under the page title")]
+    subtitle: Option<String>,
+
+    #[arg(
+        long,
+        help = "Load the Inter and Material Icons fonts from Google Fonts instead of the built-in icon font"
+    )]
+    cdn: bool,
+
+    #[arg(
+        long,
+        help = "Use 403.html, 404.html and 500.html from the served directory as error response bodies"
+    )]
+    error_pages: bool,
+
+    #[arg(
+        long,
+        help = "Watch served directories and drop cached files as soon as they change on disk"
+    )]
+    watch: bool,
+
+    #[arg(long, help = "Include dotfiles such as .git in directory listings")]
+    show_hidden: bool,
+
+    #[arg(
+        long,
+        help = "Also answer 404 for direct requests to entries matched by a .serveignore; by default they are only left out of listings"
+    )]
+    strict_ignore: bool,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "List directories before files; pass `--sort-dirs-first false` to mix them by the sort key"
+    )]
+    sort_dirs_first: bool,
+
+    #[arg(
+        long,
+        help = "Follow symlinks that resolve inside the served directory; by default any symlink is refused with 403"
+    )]
+    follow_symlinks: bool,
+
+    #[arg(
+        long,
+        default_value = "8",
+        help = "Maximum directory depth walked by /_sfs/search"
+    )]
+    search_depth: usize,
+
+    #[arg(
+        long,
+        default_value = "64",
+        help = "Reject request paths with more segments than this with 400"
+    )]
+    max_path_depth: usize,
+
+    #[arg(
+        long,
+        default_value = "100000",
+        help = "Read at most this many entries of a directory for the listing; 0 means unlimited"
+    )]
+    max_listing_entries: usize,
+
+    #[arg(
+        long,
+        default_value = "4G",
+        value_parser = parse_size,
+        help = "Largest accepted upload body, e.g. 500M; larger uploads get 413. 0 means unlimited"
+    )]
+    max_upload_size: u64,
+
+    #[arg(
+        long,
+        help = "Move deleted entries into a .trash folder of the mount instead of removing them"
+    )]
+    soft_delete: bool,
+
+    #[arg(
+        long,
+        default_value = "0",
+        help = "Maximum simultaneous file transfers, extra requests get 503; 0 means unlimited"
+    )]
+    max_connections: usize,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value = "0",
+        help = "Answer 408 if a response has not started within this many seconds; \
+                streaming an already started body and uploads are not limited. 0 disables"
+    )]
+    request_timeout: u64,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Append requests in Common Log Format to this file"
+    )]
+    access_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Persist download counts shown at /_sfs/stats to this JSON file across restarts"
+    )]
+    stats_file: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with = "verbose",
+        help = "Only log warnings and errors, without per-request lines"
+    )]
+    quiet: bool,
+
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Enable debug logging; repeat (-vv) for trace. RUST_LOG takes precedence"
+    )]
+    verbose: u8,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "pretty",
+        help = "Log output format; json writes one structured event per line for log collectors"
+    )]
+    log_format: log::LogFormat,
 }
 
 #[derive(Serialize)]
@@ -56,206 +506,1467 @@ struct FileEntry {
     is_dir: bool,
     size: Option<u64>,
     url: String,
+    // Unix时间戳（秒）
+    modified: Option<u64>,
+    is_symlink: bool,
+    // 链接的内容，只在跟随符号链接且链接指向挂载目录内时给出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+// `?stat=1` 返回的单个文件信息
+#[derive(Serialize)]
+struct FileStat {
+    name: String,
+    size: u64,
+    // Unix时间戳（秒）
+    modified: Option<u64>,
+    mime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct DownloadQuery {
     download: Option<String>,
+    #[serde(default)]
+    sort: SortKey,
+    #[serde(default)]
+    order: SortOrder,
+    format: Option<String>,
+    qr: Option<String>,
+    view: Option<String>,
+    thumb: Option<String>,
+    checksum: Option<String>,
+    stat: Option<String>,
+    level: Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortKey {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+}
+
+impl SortOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+// Range请求解析结果
+#[derive(Clone, Copy, PartialEq)]
+enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
 }
+
 #[derive(Clone)]
 struct CachedFile {
-    data: Arc<Vec<u8>>,
+    // Bytes是引用计数的，clone不会复制文件内容
+    data: Bytes,
     modified: SystemTime,
+    // `--sniff-mime` 时按内容识别出的类型，之后命中缓存不用再识别
+    mime: Option<Mime>,
 }
 
 #[derive(Clone)]
 struct AppState {
-    root_dir: PathBuf,
+    mounts: Arc<Vec<mount::Mount>>,
+    // 单文件模式下分享的文件名，位于根挂载点的目录中
+    single_file: Option<String>,
     file_cache: Cache<PathBuf, CachedFile>,
+    // 不超过该大小的文件才进入缓存，`--no-cache` 时为0
+    cache_max_file_size: u64,
+    cache_after: u32,
+    // `--cache-after` 大于1时记录还没有缓存的文件被请求了几次
+    cache_requests: Cache<PathBuf, u32>,
+    thumb_cache: thumbnail::ThumbCache,
+    checksum_cache: checksum::ChecksumCache,
+    index_file: Option<String>,
+    directory_listing: bool,
+    rate_limit: usize,
+    rate_tiers: Arc<rate_limiter::RateTiers>,
+    client_buckets: Option<rate_limiter::ClientBuckets>,
+    global_bucket: Option<Arc<rate_limiter::Bucket>>,
+    credentials: Arc<Vec<String>>,
+    ip_rules: Arc<ip_filter::IpRules>,
+    show_hidden: bool,
+    strict_ignore: bool,
+    sort_dirs_first: bool,
+    follow_symlinks: bool,
+    search_depth: usize,
+    max_path_depth: usize,
+    max_listing_entries: usize,
+    transfer_limit: Option<Arc<Semaphore>>,
+    access_log: Option<log::AccessLog>,
+    quiet: bool,
+    log_format: log::LogFormat,
+    cache_control: HeaderValue,
+    mime_overrides: Arc<HashMap<String, Mime>>,
+    sniff_mime: bool,
+    scheme: &'static str,
+    // 为空或形如 `/files`，不带结尾的斜杠
+    base_path: String,
+    theme: templates::Theme,
+    branding: Arc<templates::Branding>,
+    cdn: bool,
+    active_streams: Arc<AtomicUsize>,
+    started_at: Instant,
+    metrics: Arc<metrics::Metrics>,
+    error_pages: bool,
+    uploads: bool,
+    max_upload_size: u64,
+    soft_delete: bool,
+    tus_uploads: tus::Uploads,
+    stats: Arc<stats::DownloadStats>,
 }
-// 套娃，用于限速
-// 避免下行速率过高导致CPU满载
-struct RateLimitedStream<S> {
-    inner: S,
-    bytes_sent: usize,
-    window_start: Instant,
-    sleep: Option<Pin<Box<Sleep>>>,
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = config::load_args()?;
+
+    log::init(args.quiet, args.verbose, args.log_format);
+    // 参数是文件时只分享这一个文件：把所在目录挂载到根路径，根路径固定对应该文件
+    let single_file = match &args.directory {
+        Some(path) if path.is_file() => {
+            if !args.mounts.is_empty() {
+                anyhow::bail!("--mount cannot be combined with a single file");
+            }
+            let file = path
+                .canonicalize()
+                .with_context(|| format!("Cannot serve {}", path.display()))?;
+            // 启动时就确认能读，不要等到每个请求都返回500
+            fs::File::open(&file).with_context(|| format!("Cannot read {}", file.display()))?;
+            // 上传和搜索会涉及同目录的其他文件，单文件模式下一律只读
+            args.read_only = true;
+            Some(file)
+        }
+        _ => None,
+    };
+    let mut mounts = Vec::new();
+    for (prefix, path) in &args.mounts {
+        if mounts.iter().any(|m: &mount::Mount| &m.prefix == prefix) {
+            anyhow::bail!("Mount prefix /{} specified more than once", prefix);
+        }
+        let root = path
+            .canonicalize()
+            .with_context(|| format!("Cannot mount {}", path.display()))?;
+        mounts.push(mount::Mount {
+            prefix: prefix.clone(),
+            root,
+        });
+    }
+    if let Some(file) = &single_file {
+        mounts.push(mount::Mount {
+            prefix: String::new(),
+            root: file.parent().unwrap_or(file).to_path_buf(),
+        });
+    } else if args.directory.is_some() || mounts.is_empty() {
+        let serve_dir = args
+            .directory
+            .clone()
+            .unwrap_or_else(|| std::env::current_dir().unwrap());
+        mounts.push(mount::Mount {
+            prefix: String::new(),
+            root: serve_dir
+                .canonicalize()
+                .with_context(|| format!("Cannot serve {}", serve_dir.display()))?,
+        });
+    }
+    // 单文件模式只需要能读该文件，所在目录不必能列出
+    if single_file.is_none() {
+        for mount in &mounts {
+            fs::read_dir(&mount.root)
+                .with_context(|| format!("Cannot list {}", mount.root.display()))?;
+        }
+    }
+    // 最长前缀优先匹配
+    mounts.sort_by_key(|m| std::cmp::Reverse(m.prefix.len()));
+
+    let access_log = match &args.access_log {
+        Some(path) => {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Cannot open access log {}", path.display()))?;
+            Some(Arc::new(Mutex::new(file)))
+        }
+        None => None,
+    };
+
+    let download_stats = Arc::new(stats::DownloadStats::load(args.stats_file.clone())?);
+
+    // JSON格式的输出交给日志采集工具，不打印带颜色的启动信息
+    if args.log_format == log::LogFormat::Pretty {
+        log::banner(&args, &mounts, single_file.as_deref());
+    }
+
+    let app_state = AppState {
+        mounts: Arc::new(mounts),
+        single_file: single_file
+            .as_ref()
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy().to_string()),
+        file_cache: file_cache_for(&args),
+        cache_max_file_size: if args.no_cache {
+            0
+        } else {
+            args.cache_max_file_size
+        },
+        cache_after: args.cache_after,
+        cache_requests: Cache::builder()
+            .max_capacity(CACHE_REQUEST_COUNTERS)
+            .time_to_idle(CACHE_REQUEST_WINDOW)
+            .build(),
+        thumb_cache: thumbnail::new_cache(),
+        checksum_cache: checksum::new_cache(),
+        index_file: (!args.no_index).then(|| args.index_file.clone()),
+        directory_listing: !args.no_directory_listing,
+        rate_limit: args.rate_limit as usize,
+        rate_tiers: Arc::new(args.tiered_rate.clone().unwrap_or_default()),
+        client_buckets: (args.rate_limit_per_ip && args.rate_limit > 0)
+            .then(|| rate_limiter::ClientBuckets::new(args.rate_limit as usize)),
+        global_bucket: (args.global_rate > 0)
+            .then(|| Arc::new(rate_limiter::Bucket::new(args.global_rate as usize))),
+        credentials: Arc::new(args.credentials.clone()),
+        ip_rules: Arc::new(ip_filter::IpRules {
+            allow: args.allow_ips.clone(),
+            deny: args.deny_ips.clone(),
+            trusted_proxies: args.trusted_proxies.clone(),
+        }),
+        show_hidden: args.show_hidden,
+        strict_ignore: args.strict_ignore,
+        sort_dirs_first: args.sort_dirs_first,
+        follow_symlinks: args.follow_symlinks,
+        search_depth: args.search_depth,
+        max_path_depth: args.max_path_depth,
+        max_listing_entries: args.max_listing_entries,
+        transfer_limit: (args.max_connections > 0)
+            .then(|| Arc::new(Semaphore::new(args.max_connections))),
+        access_log,
+        quiet: args.quiet,
+        log_format: args.log_format,
+        cache_control: cache_control_for(&args),
+        mime_overrides: Arc::new(args.mime_overrides.iter().cloned().collect()),
+        sniff_mime: args.sniff_mime,
+        scheme: args.scheme(),
+        base_path: args.base_path.clone(),
+        theme: args.theme,
+        branding: Arc::new(templates::Branding {
+            title: args.title.clone(),
+            subtitle: args.subtitle.clone(),
+        }),
+        cdn: args.cdn,
+        active_streams: Arc::new(AtomicUsize::new(0)),
+        started_at: Instant::now(),
+        metrics: Arc::default(),
+        error_pages: args.error_pages,
+        uploads: !args.read_only,
+        max_upload_size: args.max_upload_size,
+        soft_delete: args.soft_delete,
+        tus_uploads: tus::new_uploads(),
+        stats: download_stats.clone(),
+    };
+
+    // watcher在main结束前一直保持，drop后监听就会停止
+    let _watcher = if args.watch {
+        Some(
+            watch::spawn(&app_state.mounts, app_state.file_cache.clone())
+                .context("Failed to watch served directories")?,
+        )
+    } else {
+        None
+    };
+
+    // 超时只限制handler产生响应头之前的时间（首字节时间），响应体开始流式传输后不再计时，
+    // 大文件下载不会被中断；上传需要在handler里读完请求体，所以只给GET路由加超时
+    let with_timeout = |route: MethodRouter<AppState>| match args.request_timeout {
+        0 => route,
+        secs => route.layer(TimeoutLayer::new(Duration::from_secs(secs))),
+    };
+    let mut root_route = with_timeout(get(handle_directory));
+    let mut path_route = with_timeout(get(handle_path));
+    if !args.read_only {
+        // PUT用于命令行上传，POST用于网页上的multipart表单，DELETE删除文件或空目录
+        root_route = root_route.post(upload::handle_form_upload);
+        path_route = path_route
+            .put(upload::handle_upload)
+            .post(upload::handle_form_upload)
+            .delete(delete::handle_delete);
+    }
+
+    let mut app = Router::new()
+        // axum会把HEAD请求交给GET路由处理，handler内部再跳过响应体
+        .route("/", root_route)
+        .route(&format!("{}/healthz", BUILTIN_PREFIX), get(handle_healthz))
+        .route(
+            &format!("{}/metrics", BUILTIN_PREFIX),
+            get(metrics::handle_metrics),
+        )
+        .route(
+            &format!("{}/stats", BUILTIN_PREFIX),
+            with_timeout(get(stats::handle_stats)),
+        )
+        .route("/*path", path_route);
+    // 只占用字体这一个地址，`/static/` 下的其他路径仍是普通文件
+    if !args.cdn {
+        app = app.route(templates::ICON_FONT_PATH, get(handle_icon_font));
+    }
+    // 单文件模式下搜索会列出同目录的其他文件，不提供，`/_sfs/search` 按普通路径返回404
+    if single_file.is_none() {
+        app = app.route(
+            &format!("{}/search", BUILTIN_PREFIX),
+            with_timeout(get(search::handle_search)),
+        );
+    }
+    // 可续传的上传（tus协议），与其他写操作一样受 `--read-only` 控制
+    // 放在保留前缀下，根目录中的 `tus/` 目录仍能正常访问
+    if !args.read_only {
+        app = app
+            .route(&format!("{}/tus", BUILTIN_PREFIX), post(tus::handle_create))
+            .route(
+                &format!("{}/tus/:id", BUILTIN_PREFIX),
+                head(tus::handle_head).patch(tus::handle_patch),
+            );
+    }
+    let app = app
+        // 上传的文件可能很大，用可配置的上限代替axum默认的2MB请求体限制
+        .layer(match args.max_upload_size {
+            0 => DefaultBodyLimit::disable(),
+            limit => DefaultBodyLimit::max(usize::try_from(limit).unwrap_or(usize::MAX)),
+        })
+        .layer(CompressionLayer::new().compress_when(compression_predicate()))
+        // 压缩中间件追加的Vary与handler写的合并成一个，不出现重复的值
+        .layer(middleware::map_response(merge_vary))
+        // 放在压缩外层，按实际发送的字节数限速
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rate_limiter::shared_limits,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::basic_auth,
+        ))
+        // 先于认证检查来源地址，被拦截的请求仍会记录日志
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            ip_filter::ip_filter,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            log::logging,
+        ));
+    let app = match cors_layer(&args) {
+        Some(cors) => app.layer(cors),
+        None => app,
+    }
+    .with_state(app_state);
+    // 挂在反向代理的子路径下时，去掉前缀再交给上面的路由，前缀以外的请求返回404
+    // nest不匹配带斜杠的 `/前缀/`，单独把它当作根目录处理
+    let app = match args.base_path.as_str() {
+        "" => app,
+        base_path => {
+            let root = app
+                .clone()
+                .map_request(|mut request: axum::extract::Request| {
+                    let uri = match request.uri().query() {
+                        Some(query) => format!("/?{}", query),
+                        None => "/".to_string(),
+                    };
+                    if let Ok(uri) = uri.parse() {
+                        *request.uri_mut() = uri;
+                    }
+                    request
+                });
+            Router::new()
+                .nest(base_path, app)
+                .route_service(&format!("{}/", base_path), root)
+        }
+    };
+
+    // 放在最外层，前缀以外的404和认证失败的响应也带上
+    let app = match args.server_header {
+        true => app.layer(middleware::map_response(server_header)),
+        false => app,
+    };
+
+    // 证书在绑定端口前加载，配置有误时直接退出
+    let tls_config = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let config = RustlsConfig::from_pem_file(cert, key)
+                .await
+                .with_context(|| format!("Failed to load TLS certificate {}", cert.display()))?;
+            Some(config)
+        }
+        _ => None,
+    };
+
+    stats::spawn_saver(download_stats.clone());
+    let result = match &args.unix {
+        Some(socket_path) => serve_unix(app, socket_path).await,
+        None => serve_tcp(app, &args, tls_config).await,
+    };
+    // 退出前把还没写入的下载统计保存下来
+    download_stats.save();
+    result
 }
 
-impl<S> RateLimitedStream<S> {
-    fn new(inner: S) -> Self {
-        Self {
-            inner,
-            bytes_sent: 0,
-            window_start: Instant::now(),
-            sleep: None,
+async fn serve_tcp(
+    app: Router,
+    args: &Args,
+    tls_config: Option<RustlsConfig>,
+) -> anyhow::Result<()> {
+    let addr = args.listen_addr();
+    let listener =
+        bind_listener(addr, args.dual_stack).with_context(|| format!("Failed to bind {}", addr))?;
+
+    log::notice(
+        "✓".green(),
+        format!(
+            "Server ready at {}",
+            format!("{}://{}", args.scheme(), addr)
+                .bright_blue()
+                .underline()
+        ),
+    );
+    log::notice("ⓘ".blue(), "Press Ctrl+C to stop".to_string());
+    log::blank_line();
+    if args.open {
+        open_browser(&format!(
+            "{}://{}{}/",
+            args.scheme(),
+            browser_addr(addr),
+            args.base_path
+        ));
+    }
+
+    let handle = Handle::new();
+    tokio::spawn(shutdown_signal(handle.clone()));
+
+    let make_service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let socket_options = SocketOptions {
+        nodelay: args.tcp_nodelay,
+        keepalive: (args.keepalive > 0).then(|| Duration::from_secs(args.keepalive)),
+    };
+    let result = match tls_config {
+        Some(config) => {
+            axum_server::from_tcp(listener)
+                .acceptor(RustlsAcceptor::new(config).acceptor(socket_options))
+                .handle(handle.clone())
+                .serve(make_service)
+                .await
         }
+        None => {
+            axum_server::from_tcp(listener)
+                .acceptor(socket_options)
+                .handle(handle.clone())
+                .serve(make_service)
+                .await
+        }
+    };
+
+    if let Err(e) = result {
+        error!("Server error: {}", e);
+    }
+    log::notice(
+        "✓".green(),
+        format!(
+            "Server stopped, {} connections were cut off",
+            handle.connection_count()
+        ),
+    );
+
+    Ok(())
+}
+
+// 监听所有地址时浏览器改为访问本机回环地址
+fn browser_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port()))
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::from((Ipv6Addr::LOCALHOST, addr.port()))
+        }
+        _ => addr,
+    }
+}
+
+// 打不开浏览器（比如没有图形界面的服务器）只给出警告，不影响服务
+fn open_browser(url: &str) {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .all(|name| std::env::var_os(name).is_none_or(|value| value.is_empty()))
+    {
+        warn!("No display available, not opening a browser for {}", url);
+        return;
+    }
+    match open::that_detached(url) {
+        Ok(()) => info!("Opened {} in the browser", url),
+        Err(e) => warn!("Failed to open a browser for {}: {}", url, e),
+    }
+}
+
+// 双栈时显式关闭IPV6_V6ONLY，不依赖系统的默认设置；IPv4客户端的地址在日志中会被还原成IPv4
+fn bind_listener(addr: SocketAddr, dual_stack: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if dual_stack {
+        socket.set_only_v6(false)?;
     }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
 }
 
-impl<S> Stream for RateLimitedStream<S>
-where
-    S: Stream<Item = Result<bytes::Bytes, std::io::Error>> + Unpin,
-{
-    type Item = Result<bytes::Bytes, std::io::Error>;
+// 每个接受的连接上设置，不依赖监听socket上的选项能否被继承
+#[derive(Clone, Copy)]
+struct SocketOptions {
+    nodelay: bool,
+    keepalive: Option<Duration>,
+}
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let now = Instant::now();
-        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
-            self.bytes_sent = 0;
-            self.window_start = now;
+impl SocketOptions {
+    fn apply(&self, stream: &tokio::net::TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
         }
+        Ok(())
+    }
+}
 
-        // 如果有sleep，优先等待
-        if let Some(ref mut sleep) = self.sleep {
-            match sleep.as_mut().poll(cx) {
-                Poll::Pending => return Poll::Pending,
-                Poll::Ready(_) => self.sleep = None,
-            }
+impl<S> Accept<tokio::net::TcpStream, S> for SocketOptions {
+    type Stream = tokio::net::TcpStream;
+    type Service = S;
+    type Future = std::future::Ready<std::io::Result<(Self::Stream, Self::Service)>>;
+
+    // 设置失败只影响延迟，连接照常处理
+    fn accept(&self, stream: Self::Stream, service: S) -> Self::Future {
+        if let Err(e) = self.apply(&stream) {
+            debug!("Failed to set socket options: {}", e);
         }
+        std::future::ready(Ok((stream, service)))
+    }
+}
 
-        match Pin::new(&mut self.inner).poll_next(cx) {
-            Poll::Ready(Some(Ok(chunk))) => {
-                self.bytes_sent += chunk.len();
-                if self.bytes_sent > RATE_LIMIT_BYTES_PER_SEC {
-                    // 超过速率，延迟到下一秒
-                    let delay = self.window_start + Duration::from_secs(1) - now;
-                    self.sleep = Some(Box::pin(tokio::time::sleep(delay)));
-                    cx.waker().wake_by_ref();
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Some(Ok(chunk)))
-                }
+// axum-server不支持Unix socket，这里直接用hyper逐个连接处理
+// 没有对端地址，请求中不会有ConnectInfo
+async fn serve_unix(app: Router, socket_path: &std::path::Path) -> anyhow::Result<()> {
+    // 上次异常退出时可能留下了socket文件，只删除socket，不误删普通文件
+    if tokio::fs::symlink_metadata(socket_path)
+        .await
+        .is_ok_and(|m| m.file_type().is_socket())
+    {
+        tokio::fs::remove_file(socket_path)
+            .await
+            .with_context(|| format!("Failed to remove stale socket {}", socket_path.display()))?;
+    }
+    let listener = tokio::net::UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind {}", socket_path.display()))?;
+
+    log::notice(
+        "✓".green(),
+        format!(
+            "Server ready at {}",
+            format!("unix:{}", socket_path.display())
+                .bright_blue()
+                .underline()
+        ),
+    );
+    log::notice("ⓘ".blue(), "Press Ctrl+C to stop".to_string());
+    log::blank_line();
+
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    let shutdown = wait_for_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let service = TowerToHyperService::new(app.clone());
+                let connection = builder
+                    .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                    .into_owned();
+                let connection = graceful.watch(connection);
+                tokio::spawn(async move {
+                    if let Err(e) = connection.await {
+                        debug!("Connection closed with error: {}", e);
+                    }
+                });
             }
-            other => other,
+            _ = &mut shutdown => break,
         }
     }
+
+    drop(listener);
+    let _ = tokio::fs::remove_file(socket_path).await;
+    log::blank_line();
+    log::notice(
+        "ⓘ".blue(),
+        format!(
+            "Shutting down, waiting up to {}s for active connections",
+            SHUTDOWN_GRACE_PERIOD.as_secs()
+        ),
+    );
+    if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        warn!("Some connections were still open after the grace period");
+    }
+    log::notice("✓".green(), "Server stopped".to_string());
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+// 收到Ctrl+C或SIGTERM后停止接受新连接，给正在进行的传输留出一段时间
+async fn shutdown_signal(handle: Handle) {
+    wait_for_signal().await;
 
-    log::init();
-    let serve_dir = args
-        .directory
-        .clone()
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
-    let serve_dir = serve_dir.canonicalize()?;
+    log::blank_line();
+    log::notice(
+        "ⓘ".blue(),
+        format!(
+            "Shutting down, waiting up to {}s for {} active connections",
+            SHUTDOWN_GRACE_PERIOD.as_secs(),
+            handle.connection_count()
+        ),
+    );
+    handle.graceful_shutdown(Some(SHUTDOWN_GRACE_PERIOD));
+}
 
-    log::banner(&args, &serve_dir);
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
 
-    let app_state = AppState {
-        root_dir: serve_dir,
-        file_cache: Cache::builder()
-            .max_capacity(CACHE_FILE_NUM_LIMIT)
-            .time_to_live(CACHE_FILE_LIFETIME)
-            .build(),
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
     };
 
-    let app = Router::new()
-        .route("/", get(handle_directory))
-        .route("/*path", get(handle_path))
-        .layer(middleware::from_fn(log::logging))
-        .layer(CorsLayer::permissive())
-        .with_state(app_state);
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    let addr = format!("{}:{}", args.bind, args.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
 
-    println!(
-        "{} Server ready at {}",
-        "✓".green(),
-        format!("http://{}", addr).bright_blue().underline()
-    );
-    println!("{} Press Ctrl+C to stop", "ⓘ".blue());
-    println!();
+// 只压缩文本类内容，图片、音视频、压缩包等本身已经压缩过，再压一遍只会浪费CPU
+fn compression_predicate() -> impl Predicate {
+    DefaultPredicate::new().and(
+        |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+            headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(is_compressible)
+        },
+    )
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/javascript"
+                | "application/x-javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-sh"
+                | "application/toml"
+                | "application/yaml"
+        )
+}
+
+// 规整成 `/files` 的形式；只允许URL中不需要编码的字符，拼进链接时不用再转义
+fn parse_base_path(value: &str) -> Result<String, String> {
+    let mut normalized = String::new();
+    for segment in value.split('/').filter(|s| !s.is_empty()) {
+        if segment == "." || segment == ".." {
+            return Err(format!("invalid base path segment `{}`", segment));
+        }
+        if !segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+        {
+            return Err(format!("unsupported character in base path `{}`", segment));
+        }
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    Ok(normalized)
+}
 
-    let result = axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
+// `*` 或者 `scheme://host[:port]` 形式的来源，不带路径
+fn parse_cors_origin(value: &str) -> Result<HeaderValue, String> {
+    let value = value.trim().trim_end_matches('/');
+    let valid = value == "*"
+        || value.split_once("://").is_some_and(|(scheme, host)| {
+            matches!(scheme, "http" | "https") && !host.is_empty() && !host.contains('/')
+        });
+    if !valid {
+        return Err(format!(
+            "expected `*` or an origin like `https://example.com`, got `{}`",
+            value
+        ));
+    }
+    HeaderValue::from_str(value).map_err(|_| format!("invalid origin `{}`", value))
+}
+
+// 所有响应带上 `Server: 名称/版本`，`--server-header false` 时不加
+async fn server_header(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::SERVER, HeaderValue::from_static(SERVER_NAME));
+    response
+}
+
+// 不指定 `--cors-origin` 时不加CORS层，浏览器只允许同源的页面读取响应
+// 方法和请求头只放开实际用到的：只读时只有GET、HEAD，可写时加上上传、删除和tus
+fn cors_layer(args: &Args) -> Option<CorsLayer> {
+    if args.cors_origins.is_empty() {
+        return None;
+    }
+    let origin = if args.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(args.cors_origins.iter().cloned())
+    };
+    let mut methods = vec![Method::GET, Method::HEAD];
+    let mut headers = vec![
+        header::AUTHORIZATION,
+        header::RANGE,
+        header::IF_RANGE,
+        header::IF_NONE_MATCH,
+        header::IF_MODIFIED_SINCE,
+    ];
+    if !args.read_only {
+        methods.extend([Method::PUT, Method::POST, Method::DELETE, Method::PATCH]);
+        headers.extend([
+            header::CONTENT_TYPE,
+            HeaderName::from_static("tus-resumable"),
+            HeaderName::from_static("upload-length"),
+            HeaderName::from_static("upload-offset"),
+            HeaderName::from_static("upload-metadata"),
+        ]);
+    }
+    Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([
+                header::CONTENT_DISPOSITION,
+                header::CONTENT_RANGE,
+                header::ACCEPT_RANGES,
+                header::ETAG,
+                header::LOCATION,
+                HeaderName::from_static("x-total-count"),
+                HeaderName::from_static("x-listing-truncated"),
+                HeaderName::from_static("x-request-id"),
+                HeaderName::from_static("x-ratelimit"),
+                HeaderName::from_static("tus-resumable"),
+                HeaderName::from_static("upload-offset"),
+                HeaderName::from_static("upload-length"),
+            ]),
     )
-    .await;
+}
 
-    if let Err(e) = result {
-        error!("Server error: {}", e);
+// 除了标准写法，也接受URL里常见的带方括号的IPv6地址
+fn parse_bind(value: &str) -> Result<IpAddr, String> {
+    let trimmed = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    trimmed
+        .parse()
+        .map_err(|_| format!("invalid IP address: {}", value))
+}
+
+// 解析 `1M`、`500K`、`2G` 这类带单位的字节数，单位按1024进位
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits_end);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size: {}", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown size unit in: {}", value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size too large: {}", value))
+}
+
+impl Args {
+    fn scheme(&self) -> &'static str {
+        if self.tls_cert.is_some() {
+            "https"
+        } else {
+            "http"
+        }
     }
 
-    Ok(())
+    // SocketAddr的Display会给IPv6地址加上方括号
+    fn listen_addr(&self) -> SocketAddr {
+        if self.dual_stack {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, self.port))
+        } else {
+            SocketAddr::new(self.bind, self.port)
+        }
+    }
+}
+
+// 存活检查，不访问文件系统，也不需要认证
+async fn handle_healthz(State(state): State<AppState>) -> Response {
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(serde_json::json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_secs": state.started_at.elapsed().as_secs(),
+        })),
+    )
+        .into_response()
+}
+
+// 字体随程序一起发布，内容只会在升级时变化
+async fn handle_icon_font() -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "font/ttf"),
+            (header::CACHE_CONTROL, "public, max-age=604800"),
+        ],
+        material_icons::FONT,
+    )
+        .into_response()
 }
 
 async fn handle_directory(
     State(state): State<AppState>,
+    method: Method,
     Query(params): Query<DownloadQuery>,
-) -> Result<Response, StatusCode> {
-    handle_path_internal(state, "".to_string(), params).await
+    headers: HeaderMap,
+) -> Response {
+    let head_only = method == Method::HEAD;
+    match handle_path_internal(
+        state.clone(),
+        "".to_string(),
+        None,
+        params,
+        headers,
+        head_only,
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err(err) => error_response(&state, err).await,
+    }
 }
 
 async fn handle_path(
     State(state): State<AppState>,
+    method: Method,
+    uri: Uri,
     Path(path): Path<String>,
     Query(params): Query<DownloadQuery>,
-) -> Result<Response, StatusCode> {
-    handle_path_internal(state, path, params).await
+    headers: HeaderMap,
+) -> Response {
+    let head_only = method == Method::HEAD;
+    let query = uri.query().map(str::to_string);
+    match handle_path_internal(state.clone(), path, query, params, headers, head_only).await {
+        Ok(response) => response,
+        Err(err) => error_response(&state, err).await,
+    }
+}
+
+// 根目录下有 404.html 等文件时用它作为错误页面的内容，状态码不变
+async fn error_response(state: &AppState, err: AppError) -> Response {
+    let status = err.status();
+    if !state.error_pages || !matches!(status.as_u16(), 403 | 404 | 500) {
+        return err.into_response();
+    }
+    let Some(root) = state.mounts.iter().find(|m| m.prefix.is_empty()) else {
+        return err.into_response();
+    };
+    let page = root.root.join(format!("{}.html", status.as_u16()));
+    match tokio::fs::read(&page).await {
+        Ok(body) => {
+            err.log();
+            (
+                status,
+                [
+                    (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                    (header::CACHE_CONTROL, "no-cache"),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Err(_) => err.into_response(),
+    }
 }
 
 async fn handle_path_internal(
     state: AppState,
     path: String,
+    query: Option<String>,
     params: DownloadQuery,
-) -> Result<Response, StatusCode> {
+    headers: HeaderMap,
+    head_only: bool,
+) -> Result<Response, AppError> {
     let decoded_path = percent_decode_str(&path).decode_utf8().map_err(|_| {
         warn!("Invalid UTF-8 in path: {}", path);
-        StatusCode::BAD_REQUEST
+        AppError::BadRequest
     })?;
+    // 规整会去掉结尾的 `/`，先记下来用于目录和文件的重定向
+    let trailing_slash = decoded_path.ends_with('/');
+    let decoded_path = normalize_request_path(&decoded_path)?;
+
+    // 在访问文件系统之前先拒绝层级过深的路径，检查代价很小
+    if decoded_path.split('/').filter(|s| !s.is_empty()).count() > state.max_path_depth {
+        warn!("Path too deep: {}", decoded_path);
+        return Err(AppError::BadRequest);
+    }
 
-    // 防止目录穿越
-    let requested_path = state.root_dir.join(&*decoded_path);
-    let canonical_path = requested_path.canonicalize().map_err(|_| {
+    // 二维码指向客户端请求的地址，单文件模式下是根路径而不是文件名
+    let request_path = decoded_path.clone();
+
+    // 单文件模式下只有根路径，对应分享的文件
+    let decoded_path = match &state.single_file {
+        Some(_) if !decoded_path.trim_matches('/').is_empty() => {
+            warn!("Path not found: {}", decoded_path);
+            return Err(AppError::NotFound);
+        }
+        Some(name) => name.clone(),
+        None => decoded_path,
+    };
+
+    let Some((mount, relative)) = state.resolve(&decoded_path) else {
+        // 根路径没有挂载目录，只列出各个挂载点
+        if decoded_path.trim_matches('/').is_empty() {
+            if params.qr.is_some() {
+                return qr::qr_response(&headers, state.scheme, &state.base_path, &request_path);
+            }
+            let json = params.format.as_deref() == Some("json") || prefers_json(&headers);
+            let base = base_url(&headers, state.scheme);
+            return serve_directory(None, &state, "", &params, json, base)
+                .await
+                .map(|response| vary_on_accept(response, params.format.as_deref()));
+        }
         warn!("Path not found: {}", decoded_path);
-        StatusCode::NOT_FOUND
-    })?;
-    if !canonical_path.starts_with(&state.root_dir) {
-        warn!("Directory traversal attempt blocked: {}", decoded_path);
-        return Err(StatusCode::FORBIDDEN);
+        return Err(AppError::NotFound);
+    };
+
+    let canonical_path = confine_path(
+        &mount.root,
+        &mount.root.join(relative),
+        state.follow_symlinks,
+    )
+    .await?;
+
+    let metadata = tokio::fs::metadata(&canonical_path)
+        .await
+        .at(&canonical_path)?;
+    if state.strict_ignore {
+        let (root, path, is_dir) = (
+            mount.root.clone(),
+            canonical_path.clone(),
+            metadata.is_dir(),
+        );
+        if tokio::task::spawn_blocking(move || serveignore::is_ignored(&root, &path, is_dir))
+            .await?
+        {
+            warn!("Ignored by .serveignore: {}", canonical_path.display());
+            return Err(AppError::NotFound);
+        }
     }
 
-    let metadata = fs::metadata(&canonical_path).map_err(|_| {
-        warn!("Cannot read metadata for: {}", canonical_path.display());
-        StatusCode::NOT_FOUND
-    })?;
+    // 和其他请求一样先确认路径存在且没有被忽略，不给不存在的路径生成二维码
+    if params.qr.is_some() {
+        return qr::qr_response(&headers, state.scheme, &state.base_path, &request_path);
+    }
 
+    // 下载统计按URL路径计数
+    let url_path = format!("/{}", decoded_path.trim_matches('/'));
     if metadata.is_file() {
-        if params.download.is_some() || !metadata.is_dir() {
-            info!("Serving file: {}", canonical_path.display());
-            return serve_file(canonical_path, &state).await;
+        if trailing_slash {
+            return Ok(redirect(&state, &decoded_path, false, query.as_deref()));
+        }
+        if let Some(size) = &params.thumb {
+            return thumbnail::thumbnail_response(&canonical_path, size, &state).await;
+        }
+        if params.stat.is_some() {
+            return stat_response(&canonical_path, &metadata, &params, &state).await;
         }
+        if let Some(algorithm) = &params.checksum {
+            return checksum::checksum_response(&canonical_path, algorithm, &state).await;
+        }
+        info!("Serving file: {}", canonical_path.display());
+        return serve_file(
+            canonical_path,
+            &url_path,
+            &state,
+            &headers,
+            &params,
+            head_only,
+        )
+        .await;
     }
 
     if metadata.is_dir() {
+        if let Some(format) = params.download.as_deref().and_then(archive::Format::parse) {
+            // 打包下载同样会暴露目录内容
+            if !state.directory_listing {
+                warn!("Directory listing disabled: {}", canonical_path.display());
+                return Err(AppError::Forbidden);
+            }
+            return archive::archive_response(
+                mount.root.clone(),
+                canonical_path,
+                format,
+                params.level.as_deref(),
+                &state,
+                head_only,
+            )
+            .await;
+        }
+        // 显式请求JSON时总是返回文件列表
+        let json = params.format.as_deref() == Some("json") || prefers_json(&headers);
+        let format = params.format.as_deref();
+        // 网页中的相对链接按 `/dir/` 解析，返回HTML之前先补上结尾的 `/`
+        if !json && !trailing_slash && !path.is_empty() {
+            let response = redirect(&state, &decoded_path, true, query.as_deref());
+            return Ok(vary_on_accept(response, format));
+        }
+        if let Some(index_file) = state.index_file.as_ref().filter(|_| !json) {
+            let index_path = canonical_path.join(index_file);
+            if tokio::fs::metadata(&index_path)
+                .await
+                .is_ok_and(|m| m.is_file())
+            {
+                // index文件本身也可能是符号链接
+                let index_path =
+                    confine_path(&mount.root, &index_path, state.follow_symlinks).await?;
+                info!("Serving index file: {}", index_path.display());
+                let url_path = format!("{}/{}", url_path.trim_end_matches('/'), index_file);
+                return serve_file(index_path, &url_path, &state, &headers, &params, head_only)
+                    .await
+                    .map(|response| vary_on_accept(response, format));
+            }
+        }
         info!("Serving directory: {}", canonical_path.display());
-        return serve_directory(canonical_path, &state.root_dir, &decoded_path).await;
+        let base = base_url(&headers, state.scheme);
+        return serve_directory(
+            Some(canonical_path),
+            &state,
+            &decoded_path,
+            &params,
+            json,
+            base,
+        )
+        .await
+        .map(|response| vary_on_accept(response, format));
     }
 
-    Err(StatusCode::NOT_FOUND)
+    // socket、fifo、设备文件等既不是普通文件也不是目录，不对外提供
+    warn!("Not a regular file: {}", canonical_path.display());
+    Err(AppError::NotFound)
 }
 
-async fn serve_file(file_path: PathBuf, state: &AppState) -> Result<Response, StatusCode> {
-    let file_size = fs::metadata(&file_path).map(|m| m.len()).unwrap_or(0);
-    let file_modified = fs::metadata(&file_path)
-        .and_then(|m| m.modified())
-        .unwrap_or(SystemTime::UNIX_EPOCH);
-    match file_size <= CACHE_FILE_SIZE_LIMIT && file_size > 0 {
-        // 小文件缓存
-        true => {
+// 直接使用已经读到的元数据，不打开文件；只有同时带 `&checksum=sha256` 时才计算摘要
+async fn stat_response(
+    file_path: &std::path::Path,
+    metadata: &fs::Metadata,
+    params: &DownloadQuery,
+    state: &AppState,
+) -> Result<Response, AppError> {
+    let sha256 = match &params.checksum {
+        Some(algorithm) => Some(checksum::digest(file_path, algorithm, state).await?),
+        None => None,
+    };
+    let modified_time = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let stat = FileStat {
+        name: file_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        size: metadata.len(),
+        modified: modified_secs(metadata),
+        mime: sniffed_type(file_path, file_path, modified_time, state)
+            .await
+            .unwrap_or_else(|| mime_for(file_path, &state.mime_overrides))
+            .to_string(),
+        sha256: sha256.map(|digest| digest.to_string()),
+    };
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Json(stat)).into_response())
+}
+
+// 防止目录穿越，每个挂载点只能访问自己目录下的文件
+// 不跟随符号链接时，canonicalize的结果与按字面规整的路径不同就说明中途经过了符号链接
+async fn confine_path(
+    root: &std::path::Path,
+    requested: &std::path::Path,
+    follow_symlinks: bool,
+) -> Result<PathBuf, AppError> {
+    let canonical = tokio::fs::canonicalize(requested).await.map_err(|_| {
+        warn!("Path not found: {}", requested.display());
+        AppError::NotFound
+    })?;
+    if !canonical.starts_with(root) {
+        warn!(
+            "Directory traversal attempt blocked: {}",
+            requested.display()
+        );
+        return Err(AppError::Forbidden);
+    }
+    if !follow_symlinks && canonical != normalize_lexically(requested) {
+        warn!("Refusing to follow symlink: {}", requested.display());
+        return Err(AppError::Forbidden);
+    }
+    Ok(canonical)
+}
+
+// 目录补上、文件去掉结尾的 `/`；逐段编码并保留 `/`，查询参数原样带上
+fn redirect(
+    state: &AppState,
+    decoded_path: &str,
+    trailing_slash: bool,
+    query: Option<&str>,
+) -> Response {
+    let mut location = state.base_path.clone();
+    for segment in decoded_path.split('/') {
+        location.push('/');
+        location.extend(utf8_percent_encode(segment, NON_ALPHANUMERIC));
+    }
+    if trailing_slash {
+        location.push('/');
+    }
+    if let Some(query) = query {
+        location.push('?');
+        location.push_str(query);
+    }
+    debug!("Redirecting {} to {}", decoded_path, location);
+    Redirect::permanent(&location).into_response()
+}
+
+// 在访问文件系统之前按字面规整请求路径：合并重复的分隔符、去掉 `.`，
+// `..` 一律拒绝，目录穿越的防护不只依赖canonicalize
+fn normalize_request_path(path: &str) -> Result<String, AppError> {
+    let mut segments = Vec::new();
+    for segment in path.split(|c| c == '/' || (cfg!(windows) && c == '\\')) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                warn!("Directory traversal attempt blocked: {}", path);
+                return Err(AppError::Forbidden);
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+fn normalize_lexically(path: &std::path::Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+async fn serve_file(
+    file_path: PathBuf,
+    url_path: &str,
+    state: &AppState,
+    req_headers: &HeaderMap,
+    params: &DownloadQuery,
+    head_only: bool,
+) -> Result<Response, AppError> {
+    let download = params.download.is_some();
+    // 解析路径之后文件可能已经被删除，此时按不存在返回404，其他I/O错误才是500
+    let file_size = tokio::fs::metadata(&file_path).await.at(&file_path)?.len();
+    // `?view` 时把文本内容包装成网页，同时带 `?download` 时仍然下载
+    if params.view.is_some() && !download {
+        return text_view(&file_path, url_path, file_size, state).await;
+    }
+    // Markdown文件默认渲染成网页，`?download` 时返回原始内容，过大的文件不渲染
+    if !download && is_markdown(&file_path) && file_size <= TEXT_VIEW_SIZE_LIMIT {
+        return markdown_view(&file_path, url_path, state).await;
+    }
+
+    // 存在预压缩的 .br/.gz 文件且客户端接受时直接发送它，Content-Type和文件名仍按原文件
+    let siblings = precompressed_siblings(&file_path).await;
+    let accepted = siblings
+        .iter()
+        .find(|(_, encoding)| accepts_encoding(req_headers, encoding));
+    let mut response = match accepted {
+        Some((data_path, encoding)) => {
+            info!(
+                "Serving precompressed {}: {}",
+                encoding,
+                data_path.display()
+            );
+            let mut response = serve_content(
+                data_path,
+                &file_path,
+                url_path,
+                state,
+                req_headers,
+                params,
+                head_only,
+            )
+            .await?;
+            response
+                .headers_mut()
+                .insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            response
+        }
+        None => {
+            serve_content(
+                &file_path,
+                &file_path,
+                url_path,
+                state,
+                req_headers,
+                params,
+                head_only,
+            )
+            .await?
+        }
+    };
+    // 同一个地址的内容随Accept-Encoding变化，告诉中间缓存分开保存
+    if !siblings.is_empty() {
+        add_vary(response.headers_mut(), header::ACCEPT_ENCODING);
+    }
+    Ok(response)
+}
+
+// 与原文件同目录的 `.br`、`.gz` 文件，按优先顺序排列
+// 只接受普通文件，不跟随符号链接，避免借此读到根目录以外的内容
+async fn precompressed_siblings(file_path: &std::path::Path) -> Vec<(PathBuf, &'static str)> {
+    let mut siblings = Vec::new();
+    for (extension, encoding) in [("br", "br"), ("gz", "gzip")] {
+        let mut sibling = file_path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(extension);
+        let sibling = PathBuf::from(sibling);
+        if tokio::fs::symlink_metadata(&sibling)
+            .await
+            .is_ok_and(|m| m.is_file())
+        {
+            siblings.push((sibling, encoding));
+        }
+    }
+    siblings
+}
+
+// 解析Accept-Encoding，q=0表示明确拒绝，`*` 匹配任意编码
+fn accepts_encoding(req_headers: &HeaderMap, encoding: &str) -> bool {
+    let Some(accept) = req_headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let quality = |name: &str| {
+        accept.split(',').find_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim();
+            if !coding.eq_ignore_ascii_case(name) {
+                return None;
+            }
+            let q = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some(q)
+        })
+    };
+    quality(encoding)
+        .or_else(|| quality("*"))
+        .is_some_and(|q| q > 0.0)
+}
+
+// 同时进行的传输数达到上限时直接拒绝，目录列表不受影响
+fn transfer_permit(
+    state: &AppState,
+    path: &std::path::Path,
+) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+    let Some(semaphore) = &state.transfer_limit else {
+        return Ok(None);
+    };
+    semaphore
+        .clone()
+        .try_acquire_owned()
+        .map(Some)
+        .map_err(|_| {
+            warn!("Transfer limit reached, rejecting: {}", path.display());
+            AppError::Unavailable
+        })
+}
+
+// 发送data_path的内容；name_path是客户端请求的文件，用来决定Content-Type和下载文件名
+async fn serve_content(
+    data_path: &std::path::Path,
+    name_path: &std::path::Path,
+    url_path: &str,
+    state: &AppState,
+    req_headers: &HeaderMap,
+    params: &DownloadQuery,
+    head_only: bool,
+) -> Result<Response, AppError> {
+    let download = params.download.is_some();
+    let file_path = data_path.to_path_buf();
+    let metadata = tokio::fs::metadata(&file_path).await.at(&file_path)?;
+    let file_size = metadata.len();
+    let file_modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    if is_not_modified(req_headers, file_size, file_modified) {
+        info!("Not modified: {}", file_path.display());
+        let mut headers = HeaderMap::new();
+        insert_validators(&mut headers, file_size, file_modified);
+        headers.insert(header::CACHE_CONTROL, state.cache_control.clone());
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    // If-Range不匹配说明文件已经变了，续传会拼出错误内容，改为返回完整文件
+    let range = req_headers
+        .get(header::RANGE)
+        .filter(|_| if_range_matches(req_headers, file_size, file_modified))
+        .and_then(|v| v.to_str().ok())
+        .map(|v| parse_range(v, file_size))
+        .unwrap_or(ByteRange::Full);
+    if range == ByteRange::Unsatisfiable {
+        warn!("Unsatisfiable range for: {}", file_path.display());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_RANGE,
+            format!("bytes */{}", file_size).parse().unwrap(),
+        );
+        return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+    }
+    // 只有完整传输才计入下载统计
+    let stats_path = (range == ByteRange::Full).then_some(url_path);
+    // HEAD请求只需要响应头，不读缓存也不打开文件
+    if head_only {
+        let sniffed = sniffed_type(data_path, name_path, file_modified, state).await;
+        let mut headers = build_headers(
+            name_path,
+            sniffed,
+            file_size,
+            file_modified,
+            download,
+            state,
+        );
+        let rate_limit = stream_rate(state, file_size);
+        if rate_limit > 0 && would_stream(state, &file_path, file_size, req_headers).await {
+            rate_limiter::announce(&mut headers, rate_limit);
+        }
+        let status = apply_range(&mut headers, range, file_size);
+        return Ok((status, headers).into_response());
+    }
+    // 空文件不用读盘也不占传输名额，直接返回空响应体
+    if file_size == 0 {
+        info!("Serving empty file: {}", file_path.display());
+        return Ok(small_file_response(
+            name_path,
+            Bytes::new(),
+            None,
+            range,
+            file_modified,
+            download,
+            state,
+            stats_path,
+        ));
+    }
+    let permit = transfer_permit(state, &file_path)?;
+    // 已经缓存的照常使用，还没缓存的由should_cache决定是读进缓存还是直接流式发送
+    let use_cache = file_size <= state.cache_max_file_size
+        && (state.file_cache.contains_key(&file_path)
+            || should_cache(state, &file_path, req_headers).await);
+    match use_cache {
+        // 小文件缓存
+        true => {
             // 缓存命中
             if let Some(cached) = state.file_cache.get(&file_path).await {
                 if cached.modified == file_modified {
                     info!("Serving cached file: {}", file_path.display());
+                    state.metrics.record_cache(true);
                     return Ok(small_file_response(
-                        &file_path,
+                        name_path,
                         cached.data.clone(),
-                        file_size,
+                        cached.mime.clone(),
+                        range,
+                        cached.modified,
+                        download,
+                        state,
+                        stats_path,
                     ));
                 } else {
                     info!(
@@ -264,83 +1975,796 @@ async fn serve_file(file_path: PathBuf, state: &AppState) -> Result<Response, St
                     );
                 }
             }
-            let data = tokio::fs::read(&file_path).await.map_err(|e| {
-                error!("Failed to read file {}: {}", file_path.display(), e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-            let arc_data = Arc::new(data);
+            let data = tokio::fs::read(&file_path).await.at(&file_path)?;
+            let data = Bytes::from(data);
+            let mime = should_sniff(data_path, name_path, state)
+                .then(|| sniff(&data))
+                .flatten();
             let cached = CachedFile {
-                data: arc_data.clone(),
+                data: data.clone(),
                 modified: file_modified,
+                mime: mime.clone(),
             };
             state.file_cache.insert(file_path.clone(), cached).await;
             info!("Small file cached: {}", file_path.display());
+            state.metrics.record_cache(false);
 
-            Ok(small_file_response(&file_path, arc_data, file_size))
+            Ok(small_file_response(
+                name_path,
+                data,
+                mime,
+                range,
+                file_modified,
+                download,
+                state,
+                stats_path,
+            ))
         }
         false => {
-            // 大文件流式传输
-            info!("Serving large file: {}", file_path.display());
-            let file = File::open(&file_path).await.map_err(|e| {
-                error!("Failed to open file {}: {}", file_path.display(), e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-            // 计算合适的缓冲区大小
-            let buffer_size = match file_size {
-                4_194_305..=16_777_216 => 256 * 1024,  // 4MB~16MB: 256KB
-                16_777_217..=67_108_928 => 512 * 1024, // 16MB~64MB: 512KB
-                67_108_929..=1_073_741_824 => 1 * 1024 * 1024, // 64MB~1GB: 1MB
-                _ => 2 * 1024 * 1024,                  // >1GB: 2MB
+            // 大文件以及暂不缓存的小文件流式传输
+            info!("Streaming file: {}", file_path.display());
+            let mut file = File::open(&file_path).await.at(&file_path)?;
+            let (start, len) = match range {
+                ByteRange::Partial(start, end) => (start, end - start + 1),
+                _ => (0, file_size),
             };
+            if start > 0 {
+                file.seek(SeekFrom::Start(start)).await.at(&file_path)?;
+            }
+            let active = ActiveStream::new(&state.active_streams);
+            let buffer_size = stream_buffer_size(file_size, active.count());
 
-            let stream = ReaderStream::with_capacity(file, buffer_size);
+            // hyper在上一块写进socket之后才会再poll响应体，慢客户端会让磁盘读取跟着停下，
+            // 内存里最多只有一个缓冲区加上socket缓冲区的数据
+            // permit和计数跟随响应体一起释放，传输结束才算完成
+            let metrics = state.metrics.clone();
+            let stream =
+                ReaderStream::with_capacity(file.take(len), buffer_size).map(move |chunk| {
+                    let _ = (&permit, &active);
+                    if let Ok(bytes) = &chunk {
+                        metrics.record_bytes(bytes.len());
+                    }
+                    chunk
+                });
             // 看起来不是很优雅
             // 也不是不行
-            let stream_limited = RateLimitedStream::new(stream);
-            let body = axum::body::Body::from_stream(stream_limited);
-            let headers = build_headers(&file_path, file_size);
-            Ok((headers, body).into_response())
+            let rate_limit = stream_rate(state, file_size);
+            // 统计包在限速外层，最后一块真正发出后才计数
+            let body = if rate_limit == 0 {
+                axum::body::Body::from_stream(state.stats.track(stream, stats_path, len))
+            } else {
+                let stream = rate_limiter::RateLimitedStream::new(stream, rate_limit);
+                axum::body::Body::from_stream(state.stats.track(stream, stats_path, len))
+            };
+            let sniffed = sniffed_type(data_path, name_path, file_modified, state).await;
+            let mut headers = build_headers(
+                name_path,
+                sniffed,
+                file_size,
+                file_modified,
+                download,
+                state,
+            );
+            if rate_limit > 0 {
+                rate_limiter::announce(&mut headers, rate_limit);
+            }
+            let status = apply_range(&mut headers, range, file_size);
+            Ok((status, headers, body).into_response())
+        }
+    }
+}
+
+// 流式传输时单个下载的限速，0表示不限速
+// 按IP共享额度时 `--rate-limit` 由shared_limits中间件处理，这里只剩分档限速
+fn stream_rate(state: &AppState, file_size: u64) -> usize {
+    let default_rate = if state.client_buckets.is_some() {
+        0
+    } else {
+        state.rate_limit
+    };
+    state.rate_tiers.rate_for(file_size).unwrap_or(default_rate)
+}
+
+// HEAD请求判断同样的GET会不会走流式传输，只查看不增加 `--cache-after` 的计数
+async fn would_stream(
+    state: &AppState,
+    file_path: &std::path::Path,
+    file_size: u64,
+    req_headers: &HeaderMap,
+) -> bool {
+    if file_size == 0 {
+        return false;
+    }
+    if file_size > state.cache_max_file_size {
+        return true;
+    }
+    if state.file_cache.contains_key(file_path) {
+        return false;
+    }
+    if no_store(req_headers) {
+        return true;
+    }
+    state.cache_after > 1
+        && state.cache_requests.get(file_path).await.unwrap_or(0) + 1 < state.cache_after
+}
+
+fn no_store(req_headers: &HeaderMap) -> bool {
+    req_headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+// 请求带 `Cache-Control: no-store` 时不放进缓存，一次性的下载不会挤掉常用的文件
+// `--cache-after N` 时同一个文件第N次被请求才缓存
+async fn should_cache(
+    state: &AppState,
+    file_path: &std::path::Path,
+    req_headers: &HeaderMap,
+) -> bool {
+    if no_store(req_headers) {
+        return false;
+    }
+    if state.cache_after <= 1 {
+        return true;
+    }
+    let requests = state.cache_requests.get(file_path).await.unwrap_or(0) + 1;
+    if requests >= state.cache_after {
+        state.cache_requests.invalidate(file_path).await;
+        true
+    } else {
+        state
+            .cache_requests
+            .insert(file_path.to_path_buf(), requests)
+            .await;
+        false
+    }
+}
+
+// 文本文件的网页预览，文件过大或不是文本时提示下载
+async fn text_view(
+    file_path: &std::path::Path,
+    url_path: &str,
+    file_size: u64,
+    state: &AppState,
+) -> Result<Response, AppError> {
+    let body_html = if file_size > TEXT_VIEW_SIZE_LIMIT {
+        info!("File too large to preview: {}", file_path.display());
+        format!(
+            r#"<p class="notice">文件超过 {}MB，无法预览，请<a href="?download=1">下载</a>后查看。</p>"#,
+            TEXT_VIEW_SIZE_LIMIT / 1024 / 1024
+        )
+    } else {
+        let data = tokio::fs::read(file_path).await.at(file_path)?;
+        let mime = mime_for(file_path, &state.mime_overrides);
+        match decode_text(&data, &mime) {
+            Some(text) => {
+                let mut html = String::from(r#"<pre class="text-view">"#);
+                for line in text.lines() {
+                    html.push_str(r#"<span class="line">"#);
+                    html.push_str(&templates::escape_html(line));
+                    html.push_str("</span>");
+                }
+                html.push_str("</pre>");
+                html
+            }
+            None => {
+                info!("Not a text file, cannot preview: {}", file_path.display());
+                r#"<p class="notice">这不是文本文件，无法预览，请<a href="?download=1">下载</a>后查看。</p>"#
+                    .to_string()
+            }
+        }
+    };
+    let page = templates::generate_document(&templates::Document {
+        current_path: url_path.trim_start_matches('/'),
+        body_html: &body_html,
+        theme: state.theme,
+        base_path: &state.base_path,
+        branding: &state.branding,
+        cdn: state.cdn,
+        download: true,
+    });
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Html(page)).into_response())
+}
+
+fn is_markdown(file_path: &std::path::Path) -> bool {
+    file_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+async fn markdown_view(
+    file_path: &std::path::Path,
+    url_path: &str,
+    state: &AppState,
+) -> Result<Response, AppError> {
+    let data = tokio::fs::read(file_path).await.at(file_path)?;
+    info!("Rendering markdown: {}", file_path.display());
+    let body_html = format!(
+        r#"<div class="markdown">{}</div>"#,
+        markdown::render(&String::from_utf8_lossy(&data))
+    );
+    let page = templates::generate_document(&templates::Document {
+        current_path: url_path.trim_start_matches('/'),
+        body_html: &body_html,
+        theme: state.theme,
+        base_path: &state.base_path,
+        branding: &state.branding,
+        cdn: state.cdn,
+        download: true,
+    });
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Html(page)).into_response())
+}
+
+// 含有NUL字节的当作二进制文件；声明为text/*但不是UTF-8的按有损方式解码
+fn decode_text<'a>(data: &'a [u8], mime: &Mime) -> Option<std::borrow::Cow<'a, str>> {
+    if data.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) => Some(text.into()),
+        Err(_) if mime.type_() == mime_guess::mime::TEXT => Some(String::from_utf8_lossy(data)),
+        Err(_) => None,
+    }
+}
+
+// 计算合适的缓冲区大小，并发流越多每个流分到的越少
+// 刚超过缓存上限的文件不按档位分配，避免缓冲区相对文件本身过大
+fn stream_buffer_size(file_size: u64, active_streams: usize) -> usize {
+    let by_size = match file_size {
+        0..=16_777_216 => 256 * 1024,              // 16MB以下: 256KB
+        16_777_217..=67_108_864 => 512 * 1024,     // 16MB~64MB: 512KB
+        67_108_865..=1_073_741_824 => 1024 * 1024, // 64MB~1GB: 1MB
+        _ => 2 * 1024 * 1024,                      // >1GB: 2MB
+    };
+    let by_fraction =
+        usize::try_from(file_size / STREAM_BUFFER_FILE_FRACTION).unwrap_or(usize::MAX);
+    let fair_share = STREAM_BUFFER_BUDGET / active_streams.max(1);
+    by_size
+        .min(by_fraction)
+        .min(fair_share)
+        .max(STREAM_BUFFER_MIN)
+}
+
+// 正在进行的流式传输计数，drop时自动减一
+struct ActiveStream {
+    counter: Arc<AtomicUsize>,
+    count: usize,
+}
+
+impl ActiveStream {
+    fn new(counter: &Arc<AtomicUsize>) -> Self {
+        let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        Self {
+            counter: counter.clone(),
+            count,
         }
     }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 部分内容响应需要改写Content-Length并补上Content-Range
+fn apply_range(headers: &mut HeaderMap, range: ByteRange, file_size: u64) -> StatusCode {
+    let ByteRange::Partial(start, end) = range else {
+        return StatusCode::OK;
+    };
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+    headers.insert(
+        header::CONTENT_RANGE,
+        format!("bytes {}-{}/{}", start, end, file_size)
+            .parse()
+            .unwrap(),
+    );
+    StatusCode::PARTIAL_CONTENT
+}
+
+// 解析Range头，仅支持单个区间
+// 多区间或格式不合法时忽略，返回完整文件
+fn parse_range(value: &str, file_size: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+    // `bytes=-500` 表示最后500字节，比文件还长时就是整个文件
+    if start.trim().is_empty() {
+        return match end.trim().parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if file_size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(file_size.saturating_sub(suffix), file_size - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.trim().parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = match end.trim() {
+        "" => None,
+        end => match end.parse::<u64>() {
+            Ok(end) => Some(end),
+            Err(_) => return ByteRange::Full,
+        },
+    };
+    if end.is_some_and(|end| end < start) {
+        return ByteRange::Full;
+    }
+    if start >= file_size {
+        return ByteRange::Unsatisfiable;
+    }
+    let last = file_size - 1;
+    ByteRange::Partial(start, end.map_or(last, |end| end.min(last)))
+}
+
+// 缓存中的数据直接按Range切片，Bytes::slice不会复制底层缓冲区
+#[allow(clippy::too_many_arguments)]
+fn small_file_response(
+    file_path: &std::path::Path,
+    data: Bytes,
+    sniffed: Option<Mime>,
+    range: ByteRange,
+    modified: SystemTime,
+    download: bool,
+    state: &AppState,
+    stats_path: Option<&str>,
+) -> Response {
+    let file_size = data.len() as u64;
+    // 读取期间文件可能被截短，区间越界时退回完整响应
+    let range = match range {
+        ByteRange::Partial(_, end) if end >= file_size => ByteRange::Full,
+        range => range,
+    };
+    let mut headers = build_headers(file_path, sniffed, file_size, modified, download, state);
+    let status = apply_range(&mut headers, range, file_size);
+    let body = cached_body(data, range);
+    state.metrics.record_bytes(body.len());
+    let body = match stats_path.filter(|_| range == ByteRange::Full) {
+        Some(path) => {
+            let len = body.len() as u64;
+            let stream = futures::stream::once(async move { Ok(body) });
+            axum::body::Body::from_stream(state.stats.track(stream, Some(path), len))
+        }
+        None => axum::body::Body::from(body),
+    };
+    (status, headers, body).into_response()
 }
 
-fn small_file_response(file_path: &PathBuf, data: Arc<Vec<u8>>, file_size: u64) -> Response {
-    let headers = build_headers(file_path, file_size);
-    let body = axum::body::Body::from(data.as_ref().clone());
-    (headers, body).into_response()
+// 直接引用缓存中的Bytes，区间请求也只是取切片，命中缓存时不复制文件内容
+fn cached_body(data: Bytes, range: ByteRange) -> Bytes {
+    match range {
+        ByteRange::Partial(start, end) => data.slice(start as usize..=end as usize),
+        _ => data,
+    }
 }
 
-fn build_headers(file_path: &PathBuf, file_size: u64) -> HeaderMap {
+// 浏览器能直接展示的类型默认inline，带 `?download` 时强制下载
+fn build_headers(
+    file_path: &std::path::Path,
+    sniffed: Option<Mime>,
+    file_size: u64,
+    modified: SystemTime,
+    download: bool,
+    state: &AppState,
+) -> HeaderMap {
     let mut headers = HeaderMap::new();
-    let content_type = mime_guess::from_path(&file_path)
-        .first_or_octet_stream()
-        .to_string();
+    let mime = sniffed.unwrap_or_else(|| mime_for(file_path, &state.mime_overrides));
     let file_name = file_path
         .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("download");
-    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
-    headers.insert(
-        header::CONTENT_LENGTH,
-        file_size.to_string().parse().unwrap(),
-    );
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "download".to_string());
+    // 非法的头部值只记录日志并换成安全的默认值，不能让单个请求panic
+    let content_type = HeaderValue::from_str(mime.as_ref()).unwrap_or_else(|_| {
+        warn!(
+            "Invalid Content-Type `{}` for: {}",
+            mime,
+            file_path.display()
+        );
+        HeaderValue::from_static("application/octet-stream")
+    });
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(file_size));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    // inline时也带上文件名，预览页面另存为时才是原来的名字
+    let kind = if !download && is_viewable(&mime) {
+        "inline"
+    } else {
+        "attachment"
+    };
     headers.insert(
         header::CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}\"", file_name)
-            .parse()
-            .unwrap(),
+        content_disposition(kind, &file_name),
     );
+    insert_validators(&mut headers, file_size, modified);
+    headers.insert(header::CACHE_CONTROL, state.cache_control.clone());
     headers
 }
 
+// 自定义的扩展名映射优先，其次才是mime_guess的猜测
+fn mime_for(file_path: &std::path::Path, overrides: &HashMap<String, Mime>) -> Mime {
+    file_path
+        .extension()
+        .and_then(|ext| overrides.get(&ext.to_string_lossy().to_lowercase()))
+        .cloned()
+        .unwrap_or_else(|| mime_guess::from_path(file_path).first_or_octet_stream())
+}
+
+// `--sniff-mime` 时扩展名猜不出类型（octet-stream）的文件再按开头的魔数识别
+// 预压缩文件的内容是压缩后的数据，不能拿来识别原文件
+fn should_sniff(
+    data_path: &std::path::Path,
+    name_path: &std::path::Path,
+    state: &AppState,
+) -> bool {
+    state.sniff_mime
+        && data_path == name_path
+        && mime_for(name_path, &state.mime_overrides) == mime_guess::mime::APPLICATION_OCTET_STREAM
+}
+
+fn sniff(data: &[u8]) -> Option<Mime> {
+    infer::get(&data[..data.len().min(SNIFF_LENGTH)]).and_then(|kind| kind.mime_type().parse().ok())
+}
+
+// 已经缓存的文件用缓存里的结果，否则只读文件开头
+async fn sniffed_type(
+    data_path: &std::path::Path,
+    name_path: &std::path::Path,
+    modified: SystemTime,
+    state: &AppState,
+) -> Option<Mime> {
+    if !should_sniff(data_path, name_path, state) {
+        return None;
+    }
+    if let Some(cached) = state.file_cache.get(data_path).await {
+        if cached.modified == modified {
+            return cached.mime.clone();
+        }
+    }
+    let mut head = Vec::with_capacity(SNIFF_LENGTH);
+    File::open(data_path)
+        .await
+        .ok()?
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut head)
+        .await
+        .ok()?;
+    sniff(&head)
+}
+
+// clap参数校验，`.ext=type/subtype` 格式，扩展名不区分大小写
+fn parse_mime_override(value: &str) -> Result<(String, Mime), String> {
+    let Some((ext, mime)) = value.split_once('=') else {
+        return Err("expected `.ext=type/subtype`".to_string());
+    };
+    let ext = ext.trim().trim_start_matches('.').to_lowercase();
+    if ext.is_empty() {
+        return Err("expected `.ext=type/subtype`".to_string());
+    }
+    let mime = mime
+        .trim()
+        .parse::<Mime>()
+        .map_err(|e| format!("invalid MIME type `{}`: {}", mime, e))?;
+    Ok((ext, mime))
+}
+
+fn file_cache_for(args: &Args) -> Cache<PathBuf, CachedFile> {
+    let builder = Cache::builder()
+        // 按文件字节数计算权重，总量超过上限后淘汰最近最少使用的文件
+        .weigher(|_, cached: &CachedFile| cached.data.len() as u32)
+        .max_capacity(args.max_cache_size)
+        .support_invalidation_closures();
+    match args.cache_ttl {
+        0 => builder.build(),
+        secs => builder.time_to_live(Duration::from_secs(secs)).build(),
+    }
+}
+
+// 文件可能随时被修改，只给较短的max-age，过期后靠ETag重新验证
+// 启用认证时不允许共享缓存保存响应
+fn cache_control_for(args: &Args) -> HeaderValue {
+    let value = match (args.cache_control_max_age, args.credentials.is_empty()) {
+        (0, _) => "no-cache".to_string(),
+        (max_age, true) => format!("public, max-age={}", max_age),
+        (max_age, false) => format!("private, max-age={}", max_age),
+    };
+    HeaderValue::from_str(&value).unwrap()
+}
+
+// RFC 5987 attr-char之外的字符都需要编码
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+// 同时给出ASCII的filename和UTF-8编码的filename*，老浏览器用前者，新浏览器用后者
+// kind为 `inline` 或 `attachment`
+fn content_disposition(kind: &'static str, file_name: &str) -> HeaderValue {
+    let ascii_name: String = file_name
+        .chars()
+        .map(|c| match c {
+            ' ' => ' ',
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded_name = utf8_percent_encode(file_name, ATTR_CHAR);
+    HeaderValue::from_str(&format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        kind, ascii_name, encoded_name
+    ))
+    .unwrap_or_else(|_| {
+        warn!("Invalid Content-Disposition for: {:?}", file_name);
+        HeaderValue::from_static(kind)
+    })
+}
+
+fn is_viewable(mime: &mime_guess::Mime) -> bool {
+    matches!(
+        mime.type_(),
+        mime_guess::mime::TEXT
+            | mime_guess::mime::IMAGE
+            | mime_guess::mime::AUDIO
+            | mime_guess::mime::VIDEO
+    ) || mime.essence_str() == "application/pdf"
+}
+
+// 由文件大小和修改时间生成弱ETag，缓存分支和磁盘分支保持一致
+fn etag_for(file_size: u64, modified: SystemTime) -> String {
+    let nanos = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", file_size, nanos)
+}
+
+fn insert_validators(headers: &mut HeaderMap, file_size: u64, modified: SystemTime) {
+    headers.insert(header::ETAG, etag_for(file_size, modified).parse().unwrap());
+    headers.insert(
+        header::LAST_MODIFIED,
+        httpdate::fmt_http_date(modified).parse().unwrap(),
+    );
+}
+
+// If-None-Match 优先于 If-Modified-Since
+fn is_not_modified(req_headers: &HeaderMap, file_size: u64, modified: SystemTime) -> bool {
+    if let Some(if_none_match) = req_headers.get(header::IF_NONE_MATCH) {
+        let etag = etag_for(file_size, modified);
+        let etag = etag.trim_start_matches("W/");
+        return if_none_match.to_str().is_ok_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+    req_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        // HTTP日期只精确到秒
+        .is_some_and(|since| {
+            let secs = |t: SystemTime| {
+                t.duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            };
+            secs(modified) <= secs(since)
+        })
+}
+
+// 没有If-Range时总是匹配
+// ETag都是弱ETag，这里和If-None-Match一样忽略W/前缀比较；日期须与Last-Modified完全一致
+fn if_range_matches(req_headers: &HeaderMap, file_size: u64, modified: SystemTime) -> bool {
+    let Some(if_range) = req_headers.get(header::IF_RANGE) else {
+        return true;
+    };
+    let Ok(value) = if_range.to_str() else {
+        return false;
+    };
+    let value = value.trim();
+    if value.ends_with('"') {
+        let etag = etag_for(file_size, modified);
+        return value.trim_start_matches("W/") == etag.trim_start_matches("W/");
+    }
+    httpdate::parse_http_date(value)
+        .is_ok_and(|date| httpdate::fmt_http_date(date) == httpdate::fmt_http_date(modified))
+}
+
+// 由请求的Host头拼出站点的绝对地址，如 `https://files.example.com`
+fn base_url(headers: &HeaderMap, scheme: &str) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    Some(format!("{}://{}", scheme, host))
+}
+
+// Accept中application/json出现在text/html之前时认为客户端想要JSON
+// 没有 `?format=json` 时返回网页还是JSON由Accept头决定，响应要带上 `Vary: Accept`
+fn vary_on_accept(mut response: Response, format: Option<&str>) -> Response {
+    if format != Some("json") {
+        add_vary(response.headers_mut(), header::ACCEPT);
+    }
+    response
+}
+
+// 所有协商过的请求头都经这里写进Vary，已经有的不重复添加
+// 能压缩的响应由压缩中间件自己加上 `Vary: accept-encoding`
+fn add_vary(headers: &mut HeaderMap, name: HeaderName) {
+    let present = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|value| value == "*" || value.eq_ignore_ascii_case(name.as_str()));
+    if !present {
+        headers.append(header::VARY, HeaderValue::from(name));
+    }
+}
+
+async fn merge_vary(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    if headers.get_all(header::VARY).iter().count() < 2 {
+        return response;
+    }
+    let mut names: Vec<String> = Vec::new();
+    for value in headers.get_all(header::VARY) {
+        for name in value.to_str().unwrap_or("").split(',').map(str::trim) {
+            if !name.is_empty() && !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&names.join(", ")) {
+        headers.insert(header::VARY, value);
+    }
+    response
+}
+
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let position = |mime: &str| {
+        accept
+            .split(',')
+            .position(|item| item.split(';').next().unwrap_or("").trim() == mime)
+    };
+    match (position("application/json"), position("text/html")) {
+        (Some(json), Some(html)) => json < html,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+// 目录中的一项，跟随符号链接时类型、大小和修改时间取自链接目标
+struct DirEntryInfo {
+    name: std::ffi::OsString,
+    is_dir: bool,
+    size: Option<u64>,
+    modified: Option<u64>,
+    is_symlink: bool,
+    target: Option<String>,
+}
+
+// 最多读取limit项，多读一项用来判断是否被截断；返回的bool表示目录里还有更多条目
+fn read_dir_entries(
+    dir_path: &std::path::Path,
+    show_hidden: bool,
+    follow_symlinks: bool,
+    mounts: &[mount::Mount],
+    ignore: &serveignore::Rules,
+    limit: usize,
+) -> Result<(Vec<DirEntryInfo>, bool), AppError> {
+    let mut entries = fs::read_dir(dir_path)
+        .at(dir_path)?
+        // 默认隐藏以 `.` 开头的文件，只影响列表，直接访问不受限制
+        .filter(|res| {
+            show_hidden
+                || res.as_ref().map_or(true, |entry| {
+                    !entry.file_name().to_string_lossy().starts_with('.')
+                })
+        })
+        // `.serveignore` 匹配的条目同样只是不列出
+        .filter(|res| {
+            res.as_ref().map_or(true, |entry| {
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                !ignore.is_ignored(&entry.path(), is_dir)
+            })
+        })
+        .map(|res| {
+            res.at(dir_path).and_then(|entry| {
+                let mut metadata = entry.metadata().at(&entry.path())?;
+                let is_symlink = metadata.is_symlink();
+                let mut target = None;
+                // 跟随符号链接时显示目标的类型和大小，悬空链接仍按链接本身显示
+                if follow_symlinks && is_symlink {
+                    if let Ok(resolved) = fs::metadata(entry.path()) {
+                        metadata = resolved;
+                    }
+                    target = link_target(&entry.path(), mounts);
+                }
+                let is_dir = metadata.is_dir();
+                Ok(DirEntryInfo {
+                    name: entry.file_name(),
+                    is_dir,
+                    size: if is_dir { None } else { Some(metadata.len()) },
+                    modified: modified_secs(&metadata),
+                    is_symlink,
+                    target,
+                })
+            })
+        })
+        .take(limit.saturating_add(1))
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let truncated = entries.len() > limit;
+    entries.truncate(limit);
+    Ok((entries, truncated))
+}
+
+// 只给出指向挂载目录内的链接内容，不暴露服务目录以外的路径
+fn link_target(path: &std::path::Path, mounts: &[mount::Mount]) -> Option<String> {
+    let canonical = fs::canonicalize(path).ok()?;
+    if !mounts
+        .iter()
+        .any(|mount| canonical.starts_with(&mount.root))
+    {
+        return None;
+    }
+    fs::read_link(path)
+        .ok()
+        .map(|target| target.to_string_lossy().to_string())
+}
+
+// 修改时间转成Unix时间戳（秒）
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+// dir_path为None时是没有根目录的虚拟根路径，只列出挂载点
 async fn serve_directory(
-    dir_path: PathBuf,
-    root_dir: &StdPath,
+    dir_path: Option<PathBuf>,
+    state: &AppState,
     current_path: &str,
-) -> Result<Response, StatusCode> {
+    params: &DownloadQuery,
+    json: bool,
+    base_url: Option<String>,
+) -> Result<Response, AppError> {
+    // 没有index文件的目录直接拒绝，相当于Apache的 `Options -Indexes`
+    if !state.directory_listing {
+        warn!("Directory listing disabled: /{}", current_path);
+        return Err(AppError::Forbidden);
+    }
     let mut entries = Vec::new();
+    let is_root = current_path.trim_matches('/').is_empty();
 
-    if dir_path != *root_dir {
+    if !is_root {
         let parent_path = if current_path.is_empty() {
             String::new()
         } else {
@@ -351,62 +2775,264 @@ async fn serve_directory(
                 String::new()
             }
         };
+        // 目录的链接都以 `/` 结尾，点击时不用再经过一次重定向
+        let parent_path = if parent_path.is_empty() {
+            parent_path
+        } else {
+            format!("{}/", parent_path)
+        };
 
         entries.push(FileEntry {
             name: "..".to_string(),
             is_dir: true,
             size: None,
-            url: format!("/{}", parent_path),
+            url: format!("{}/{}", state.base_path, parent_path),
+            modified: None,
+            is_symlink: false,
+            target: None,
         });
     }
 
-    let mut dir_entries = fs::read_dir(&dir_path)
-        .map_err(|e| {
-            error!("Failed to read directory {}: {}", dir_path.display(), e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .map(|res| {
-            res.map_err(|e| {
-                error!("Failed to read entry: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })
-            .and_then(|entry| {
-                let file_name = entry.file_name();
-                let metadata = entry.metadata().map_err(|e| {
-                    error!("Failed to read metadata: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?;
-                let is_dir = metadata.is_dir();
-                let size = if is_dir { None } else { Some(metadata.len()) };
-                Ok((file_name, is_dir, size))
+    // 目录很大时逐个stat会比较慢，放到blocking线程池里执行
+    // 条目数有上限，超大的目录只读取前面一部分，不会把所有条目都放进内存
+    let limit = match state.max_listing_entries {
+        0 => usize::MAX,
+        limit => limit,
+    };
+    let (mut dir_entries, truncated) = match dir_path {
+        Some(dir_path) => {
+            let (show_hidden, follow_symlinks) = (state.show_hidden, state.follow_symlinks);
+            let mounts = state.mounts.clone();
+            let root = state
+                .resolve(current_path)
+                .map(|(mount, _)| mount.root.clone());
+            tokio::task::spawn_blocking(move || {
+                let ignore = root
+                    .map(|root| serveignore::Rules::for_dir(&root, &dir_path))
+                    .unwrap_or_default();
+                read_dir_entries(
+                    &dir_path,
+                    show_hidden,
+                    follow_symlinks,
+                    &mounts,
+                    &ignore,
+                    limit,
+                )
+                .inspect(|(_, truncated)| {
+                    if *truncated {
+                        warn!(
+                            "Directory {} has more than {} entries, listing truncated",
+                            dir_path.display(),
+                            limit
+                        );
+                    }
+                })
             })
+            .await??
+        }
+        None => (Vec::new(), false),
+    };
+
+    // 根路径下把挂载点当作目录列出，同名的真实目录会被挂载点覆盖
+    if is_root {
+        for mount in state.mounts.iter().filter(|m| !m.prefix.is_empty()) {
+            let name = std::ffi::OsString::from(&mount.prefix);
+            dir_entries.retain(|entry| entry.name != name);
+            let modified = tokio::fs::metadata(&mount.root)
+                .await
+                .ok()
+                .and_then(|m| modified_secs(&m));
+            dir_entries.push(DirEntryInfo {
+                name,
+                is_dir: true,
+                size: None,
+                modified,
+                is_symlink: false,
+                target: None,
+            });
+        }
+    }
+
+    // 默认目录排在文件前面，组内按指定字段排序，相同时按名称；`..` 不参与排序，总在最前
+    dir_entries.sort_by(|a, b| {
+        let dirs_first = if state.sort_dirs_first {
+            b.is_dir.cmp(&a.is_dir)
+        } else {
+            std::cmp::Ordering::Equal
+        };
+        dirs_first.then_with(|| {
+            let ordering = match params.sort {
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+                SortKey::Modified => a
+                    .modified
+                    .cmp(&b.modified)
+                    .then_with(|| a.name.cmp(&b.name)),
+            };
+            match params.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
         })
-        .collect::<Result<Vec<_>, StatusCode>>()?;
-    
-    // (file_name, is_dir, size)
-    dir_entries.sort_by(|a, b| match (a.1, b.1) {
-        (true, false) => std::cmp::Ordering::Less,
-        (false, true) => std::cmp::Ordering::Greater,
-        _ => a.0.cmp(&b.0),
     });
 
-    for (file_name, is_dir, size) in dir_entries {
-        let file_name_str = file_name.to_string_lossy().to_string();
+    // 统计整个目录，分页只影响返回的条目
+    let summary =
+        dir_entries
+            .iter()
+            .fold(templates::DirSummary::default(), |mut summary, entry| {
+                summary.add(entry.is_dir, entry.size);
+                summary
+            });
+    let total = dir_entries.len();
+    let per_page = match params.per_page {
+        Some(0) | None => DEFAULT_PAGE_SIZE,
+        Some(n) => n.min(MAX_PAGE_SIZE),
+    };
+    let page = params.page.unwrap_or(1).max(1);
+
+    let page_entries = dir_entries
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page);
+    for entry in page_entries {
+        let file_name_str = entry.name.to_string_lossy().to_string();
         let entry_path = if current_path.is_empty() {
             file_name_str.clone()
         } else {
             format!("{}/{}", current_path.trim_end_matches('/'), file_name_str)
         };
-        let encoded_path = utf8_percent_encode(&entry_path, NON_ALPHANUMERIC).to_string();
+        let mut encoded_path = utf8_percent_encode(&entry_path, NON_ALPHANUMERIC).to_string();
+        if entry.is_dir {
+            encoded_path.push('/');
+        }
 
         entries.push(FileEntry {
             name: file_name_str,
-            is_dir,
-            size,
-            url: format!("/{}", encoded_path),
+            is_dir: entry.is_dir,
+            size: entry.size,
+            url: format!("{}/{}", state.base_path, encoded_path),
+            modified: entry.modified,
+            is_symlink: entry.is_symlink,
+            target: entry.target,
         });
     }
 
-    let html = templates::generate_html(&entries, current_path);
-    Ok(Html(html).into_response())
+    // 目录内容随时变化，每次都要重新请求
+    // JSON保持数组格式，总条目数放在响应头里
+    if json {
+        return Ok((
+            [
+                (header::CACHE_CONTROL, "no-cache".to_string()),
+                (HeaderName::from_static("x-total-count"), total.to_string()),
+                (
+                    HeaderName::from_static("x-listing-truncated"),
+                    truncated.to_string(),
+                ),
+            ],
+            Json(entries),
+        )
+            .into_response());
+    }
+
+    let html = templates::generate_html(&templates::Listing {
+        entries: &entries,
+        is_empty: total == 0,
+        truncated: truncated.then_some(limit),
+        current_path,
+        sort: params.sort,
+        order: params.order,
+        summary,
+        pagination: templates::Pagination {
+            page,
+            per_page,
+            total,
+        },
+        theme: state.theme,
+        uploads: state.uploads,
+        base_url,
+        base_path: &state.base_path,
+        branding: &state.branding,
+        cdn: state.cdn,
+    });
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Html(html)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    // 刚超过默认4MB缓存上限的文件按1/32截断，不会直接拿到256KB
+    #[test]
+    fn buffer_clamped_just_above_cache_limit() {
+        assert_eq!(stream_buffer_size(4 * MB + 1, 1), 128 * 1024);
+        assert_eq!(stream_buffer_size(6 * MB, 1), 192 * 1024);
+        assert_eq!(stream_buffer_size(8 * MB, 1), 256 * 1024);
+    }
+
+    #[test]
+    fn buffer_tier_boundaries() {
+        assert_eq!(stream_buffer_size(16_777_216, 1), 256 * 1024);
+        assert_eq!(stream_buffer_size(16_777_217, 1), 512 * 1024);
+        assert_eq!(stream_buffer_size(64 * MB, 1), 512 * 1024);
+        assert_eq!(stream_buffer_size(64 * MB + 1, 1), 1024 * 1024);
+        assert_eq!(stream_buffer_size(1024 * MB, 1), 1024 * 1024);
+        assert_eq!(stream_buffer_size(1024 * MB + 1, 1), 2 * 1024 * 1024);
+    }
+
+    // `--cache-max-file-size` 调小或 `--no-cache` 时很小的文件也会走流式传输
+    #[test]
+    fn buffer_floor_for_tiny_files() {
+        assert_eq!(stream_buffer_size(0, 1), STREAM_BUFFER_MIN);
+        assert_eq!(stream_buffer_size(100 * 1024, 1), STREAM_BUFFER_MIN);
+    }
+
+    // 64MB的总预算平均分给正在进行的流
+    #[test]
+    fn buffer_shrinks_with_active_streams() {
+        let file_size = 2048 * MB;
+        assert_eq!(stream_buffer_size(file_size, 1), 2 * 1024 * 1024);
+        assert_eq!(stream_buffer_size(file_size, 64), 1024 * 1024);
+        assert_eq!(stream_buffer_size(file_size, 1024), 64 * 1024);
+        assert_eq!(stream_buffer_size(file_size, 10_000), STREAM_BUFFER_MIN);
+    }
+
+    #[test]
+    fn buffer_with_no_active_streams() {
+        assert_eq!(stream_buffer_size(2048 * MB, 0), 2 * 1024 * 1024);
+    }
+
+    // 两次命中得到的响应体都指向缓存里的同一块内存
+    #[tokio::test]
+    async fn cache_hit_shares_buffer() {
+        let cache: Cache<PathBuf, CachedFile> = Cache::new(1);
+        let data = Bytes::from(vec![7u8; 4 * 1024 * 1024]);
+        let cached = data.as_ptr();
+        cache
+            .insert(
+                PathBuf::from("big.bin"),
+                CachedFile {
+                    data,
+                    modified: SystemTime::UNIX_EPOCH,
+                    mime: None,
+                },
+            )
+            .await;
+        for range in [
+            ByteRange::Full,
+            ByteRange::Full,
+            ByteRange::Partial(1024, 2047),
+        ] {
+            let hit = cache.get(&PathBuf::from("big.bin")).await.unwrap();
+            let body = axum::body::Body::from(cached_body(hit.data, range));
+            let sent = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let offset = match range {
+                ByteRange::Partial(start, _) => start as usize,
+                _ => 0,
+            };
+            assert_eq!(sent.as_ptr(), cached.wrapping_add(offset));
+        }
+    }
 }
@@ -0,0 +1,180 @@
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+// 按一秒的窗口统计发送字节数，吞吐量取上一个完整窗口的值
+struct ThroughputWindow {
+    start: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl Default for ThroughputWindow {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            current: 0,
+            previous: 0,
+        }
+    }
+}
+
+impl ThroughputWindow {
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.start);
+        if elapsed >= THROUGHPUT_WINDOW * 2 {
+            // 中间有整秒没有发送任何数据
+            self.previous = 0;
+            self.current = 0;
+            self.start = now;
+        } else if elapsed >= THROUGHPUT_WINDOW {
+            self.previous = self.current;
+            self.current = 0;
+            self.start += THROUGHPUT_WINDOW;
+        }
+    }
+}
+
+// 简单的计数器集合，只用原子变量，不引入额外依赖
+#[derive(Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    // 按状态码类别计数，下标0对应1xx
+    responses: [AtomicU64; 5],
+    bytes_served: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    throughput: Mutex<ThroughputWindow>,
+}
+
+impl Metrics {
+    pub fn record_response(&self, status: StatusCode) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(counter) = self.responses.get(status.as_u16() as usize / 100 - 1) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_bytes(&self, bytes: usize) {
+        self.bytes_served.fetch_add(bytes as u64, Ordering::Relaxed);
+        let mut window = self.throughput.lock().unwrap();
+        window.roll(Instant::now());
+        window.current += bytes as u64;
+    }
+
+    // 最近一秒内所有传输加起来的字节数
+    pub fn throughput(&self) -> u64 {
+        let mut window = self.throughput.lock().unwrap();
+        window.roll(Instant::now());
+        window.previous
+    }
+
+    pub fn record_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Prometheus文本格式
+pub async fn handle_metrics(State(state): State<AppState>) -> Response {
+    let metrics = &state.metrics;
+    let mut body = String::new();
+    let mut counter = |name: &str, help: &str, value: u64| {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} counter", name);
+        let _ = writeln!(body, "{} {}", name, value);
+    };
+    counter(
+        "http_file_server_requests_total",
+        "Total HTTP requests, excluding /_sfs/metrics",
+        metrics.requests.load(Ordering::Relaxed),
+    );
+    counter(
+        "http_file_server_bytes_served_total",
+        "File bytes sent to clients",
+        metrics.bytes_served.load(Ordering::Relaxed),
+    );
+    counter(
+        "http_file_server_cache_hits_total",
+        "Small files served from the memory cache",
+        metrics.cache_hits.load(Ordering::Relaxed),
+    );
+    counter(
+        "http_file_server_cache_misses_total",
+        "Small files read from disk into the memory cache",
+        metrics.cache_misses.load(Ordering::Relaxed),
+    );
+
+    let _ = writeln!(
+        body,
+        "# HELP http_file_server_responses_total HTTP responses by status class"
+    );
+    let _ = writeln!(body, "# TYPE http_file_server_responses_total counter");
+    for (i, count) in metrics.responses.iter().enumerate() {
+        let _ = writeln!(
+            body,
+            "http_file_server_responses_total{{class=\"{}xx\"}} {}",
+            i + 1,
+            count.load(Ordering::Relaxed)
+        );
+    }
+
+    let _ = writeln!(
+        body,
+        "# HELP http_file_server_active_streams File transfers currently streaming from disk"
+    );
+    let _ = writeln!(body, "# TYPE http_file_server_active_streams gauge");
+    let _ = writeln!(
+        body,
+        "http_file_server_active_streams {}",
+        state.active_streams.load(Ordering::Relaxed)
+    );
+
+    let _ = writeln!(
+        body,
+        "# HELP http_file_server_throughput_bytes_per_second Aggregate file bytes sent during the last second"
+    );
+    let _ = writeln!(
+        body,
+        "# TYPE http_file_server_throughput_bytes_per_second gauge"
+    );
+    let _ = writeln!(
+        body,
+        "http_file_server_throughput_bytes_per_second {}",
+        metrics.throughput()
+    );
+    if let Some(bucket) = &state.global_bucket {
+        let _ = writeln!(
+            body,
+            "# HELP http_file_server_global_rate_bytes_per_second Configured --global-rate cap"
+        );
+        let _ = writeln!(
+            body,
+            "# TYPE http_file_server_global_rate_bytes_per_second gauge"
+        );
+        let _ = writeln!(
+            body,
+            "http_file_server_global_rate_bytes_per_second {}",
+            bucket.limit()
+        );
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
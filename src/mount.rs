@@ -0,0 +1,44 @@
+use crate::{AppState, BUILTIN_PREFIX};
+use std::path::PathBuf;
+
+// 挂载点，prefix为空表示挂载在根路径
+// 为了让根目录列表能直接列出挂载点，prefix只允许单级路径
+#[derive(Clone)]
+pub struct Mount {
+    pub prefix: String,
+    pub root: PathBuf,
+}
+
+// clap参数校验，`/docs=/srv/docs` 格式
+pub fn parse_mount(value: &str) -> Result<(String, PathBuf), String> {
+    let (prefix, path) = value
+        .split_once('=')
+        .ok_or_else(|| "expected `/PREFIX=PATH`".to_string())?;
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() || prefix.contains('/') || prefix == "." || prefix == ".." {
+        return Err(format!("invalid mount prefix `{}`", prefix));
+    }
+    // 内置接口占用的前缀不能再挂载目录
+    if prefix == BUILTIN_PREFIX.trim_start_matches('/') {
+        return Err(format!("mount prefix `{}` is reserved", prefix));
+    }
+    if path.is_empty() {
+        return Err("mount path must not be empty".to_string());
+    }
+    Ok((prefix.to_string(), PathBuf::from(path)))
+}
+
+impl AppState {
+    // 按最长前缀匹配挂载点，返回挂载点和挂载点内的相对路径
+    pub fn resolve<'a>(&self, path: &'a str) -> Option<(&Mount, &'a str)> {
+        let path = path.trim_start_matches('/');
+        self.mounts.iter().find_map(|mount| {
+            if mount.prefix.is_empty() {
+                return Some((mount, path));
+            }
+            let rest = path.strip_prefix(mount.prefix.as_str())?;
+            (rest.is_empty() || rest.starts_with('/'))
+                .then(|| (mount, rest.trim_start_matches('/')))
+        })
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{base_url, error::AppError};
+use axum::{
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use qrcode::{render::svg, QrCode};
+use tracing::{error, warn};
+
+// 生成指向该路径的二维码，主机名取自请求的Host头，方便手机扫码访问
+// 只在 `?qr` 时调用，不影响正常下载
+pub fn qr_response(
+    headers: &HeaderMap,
+    scheme: &str,
+    base_path: &str,
+    decoded_path: &str,
+) -> Result<Response, AppError> {
+    let Some(base) = base_url(headers, scheme) else {
+        warn!("QR code requested without Host header: {}", decoded_path);
+        return Err(AppError::BadRequest);
+    };
+    // 编码方式与目录列表中的url一致
+    let url = format!(
+        "{}{}/{}",
+        base,
+        base_path,
+        utf8_percent_encode(decoded_path.trim_start_matches('/'), NON_ALPHANUMERIC)
+    );
+    let code = QrCode::new(url.as_bytes()).map_err(|e| {
+        error!("Failed to generate QR code for {}: {}", url, e);
+        AppError::Internal
+    })?;
+    let image = code.render::<svg::Color>().min_dimensions(256, 256).build();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        image,
+    )
+        .into_response())
+}
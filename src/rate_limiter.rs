@@ -0,0 +1,260 @@
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use moka::sync::Cache;
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::time::{Duration, Instant, Sleep};
+
+const SHARED_CHUNK_SIZE: usize = 64 * 1024;
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(60); // 一分钟没有下载的IP不再保留额度
+
+static RATE_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit");
+
+// 在响应头里告诉客户端限速是多少字节每秒，方便排查下载为什么慢
+// 多个限速同时生效时实际速度不会超过其中最小的，只保留最小值
+pub fn announce(headers: &mut HeaderMap, limit: usize) {
+    let current = headers
+        .get(&RATE_LIMIT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if current.is_none_or(|current| limit < current) {
+        headers.insert(RATE_LIMIT.clone(), HeaderValue::from(limit));
+    }
+}
+
+// 以一秒为窗口的发送额度，可以被多个流共享（同一客户端或整个服务器）
+pub struct Bucket {
+    limit: usize,
+    // (本窗口已发送的字节数, 窗口开始时间)
+    window: Mutex<(usize, Instant)>,
+}
+
+impl Bucket {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            window: Mutex::new((0, Instant::now())),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // 额度还有剩余时返回None，否则返回下一个窗口开始的时间
+    fn exhausted_until(&self, now: Instant) -> Option<Instant> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        // 一块数据可能比每秒的额度还大，超出的部分记到后面的窗口里，不能随窗口一起清零
+        let elapsed = now.duration_since(window.1).as_secs();
+        if elapsed > 0 {
+            let allowance = self.limit.saturating_mul(elapsed as usize);
+            if window.0 > allowance {
+                window.0 -= allowance;
+                window.1 += Duration::from_secs(elapsed);
+            } else {
+                *window = (0, now);
+            }
+        }
+        (window.0 >= self.limit).then(|| window.1 + Duration::from_secs(1))
+    }
+
+    fn consume(&self, bytes: usize) {
+        self.window.lock().unwrap_or_else(|e| e.into_inner()).0 += bytes;
+    }
+}
+
+// `--rate-limit-per-ip` 时按客户端IP共享额度，一个客户端开再多连接也只能用到 `--rate-limit`
+#[derive(Clone)]
+pub struct ClientBuckets {
+    cache: Cache<IpAddr, Arc<Bucket>>,
+    limit: usize,
+}
+
+impl ClientBuckets {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            cache: Cache::builder().time_to_idle(IDLE_BUCKET_TTL).build(),
+            limit,
+        }
+    }
+
+    fn bucket(&self, ip: IpAddr) -> Arc<Bucket> {
+        self.cache
+            .get_with(ip, || Arc::new(Bucket::new(self.limit)))
+    }
+}
+
+// 套娃，用于限速
+// 避免下行速率过高导致CPU满载
+pub struct RateLimitedStream<S> {
+    inner: S,
+    bucket: Arc<Bucket>,
+    // 共享额度时每发送一块就访问一次缓存，传输中的客户端不会因为空闲被淘汰
+    client: Option<(ClientBuckets, IpAddr)>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> RateLimitedStream<S> {
+    pub fn new(inner: S, limit: usize) -> Self {
+        Self {
+            inner,
+            bucket: Arc::new(Bucket::new(limit)),
+            client: None,
+            sleep: None,
+        }
+    }
+
+    // `--global-rate` 时所有响应共用同一个额度，用完后所有流一起等到下一秒
+    fn with_bucket(inner: S, bucket: Arc<Bucket>) -> Self {
+        Self {
+            inner,
+            bucket,
+            client: None,
+            sleep: None,
+        }
+    }
+
+    fn shared(inner: S, buckets: &ClientBuckets, ip: IpAddr) -> Self {
+        Self {
+            inner,
+            bucket: buckets.bucket(ip),
+            client: Some((buckets.clone(), ip)),
+            sleep: None,
+        }
+    }
+}
+
+impl<S> Stream for RateLimitedStream<S>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Unpin,
+{
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            // 如果有sleep，优先等待
+            if let Some(sleep) = self.sleep.as_mut() {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.sleep = None;
+            }
+
+            // 本窗口额度用完，延迟到下一秒再读
+            // 先判断再读取，已经读出来的chunk不能丢
+            match self.bucket.exhausted_until(Instant::now()) {
+                Some(deadline) => {
+                    self.sleep = Some(Box::pin(tokio::time::sleep_until(deadline)));
+                }
+                None => break,
+            }
+        }
+
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.bucket.consume(chunk.len());
+            if let Some((buckets, ip)) = &self.client {
+                buckets.cache.get(ip);
+            }
+        }
+        poll
+    }
+}
+
+// 把响应体接到共享的额度上：`--rate-limit-per-ip` 时按客户端，`--global-rate` 时按整个服务器
+// 包括缓存的小文件和目录打包；Unix socket连接没有IP，只受全局额度限制
+pub async fn shared_limits(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = match (&state.client_buckets, connect_info) {
+        (Some(buckets), Some(ConnectInfo(addr))) => Some((
+            buckets,
+            state.ip_rules.client_ip(addr.ip(), request.headers()),
+        )),
+        _ => None,
+    };
+    if client.is_none() && state.global_bucket.is_none() {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = next.run(request).await.into_parts();
+    // 错误和重定向的响应体很小，不值得提示
+    if parts.status.is_success() {
+        if let Some((buckets, _)) = &client {
+            announce(&mut parts.headers, buckets.limit);
+        }
+        if let Some(bucket) = &state.global_bucket {
+            announce(&mut parts.headers, bucket.limit());
+        }
+    }
+    // 缓存的小文件整个是一块，切小之后额度才能按窗口生效
+    let stream = body
+        .into_data_stream()
+        .flat_map(|chunk| {
+            let pieces: Vec<_> = match chunk {
+                Ok(bytes) => (0..bytes.len())
+                    .step_by(SHARED_CHUNK_SIZE)
+                    .map(|start| Ok(bytes.slice(start..bytes.len().min(start + SHARED_CHUNK_SIZE))))
+                    .collect(),
+                Err(e) => vec![Err(std::io::Error::other(e))],
+            };
+            futures::stream::iter(pieces)
+        })
+        .boxed();
+    let stream = match client {
+        Some((buckets, ip)) => RateLimitedStream::shared(stream, buckets, ip).boxed(),
+        None => stream,
+    };
+    let stream = match &state.global_bucket {
+        Some(bucket) => RateLimitedStream::with_bucket(stream, bucket.clone()).boxed(),
+        None => stream,
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+// 按文件大小分档限速，例如 `1G:5M,100M:20M`
+// 文件大小不小于某一档阈值时使用该档速率，多档命中时取阈值最大的
+#[derive(Clone, Default)]
+pub struct RateTiers(Vec<(u64, usize)>);
+
+impl RateTiers {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut tiers = value
+            .split(',')
+            .filter(|tier| !tier.trim().is_empty())
+            .map(|tier| {
+                let (threshold, rate) = tier
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected `SIZE:RATE`, got `{}`", tier))?;
+                Ok((
+                    crate::parse_size(threshold)?,
+                    crate::parse_size(rate)? as usize,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        tiers.sort_by_key(|(threshold, _)| std::cmp::Reverse(*threshold));
+        Ok(Self(tiers))
+    }
+
+    pub fn rate_for(&self, file_size: u64) -> Option<usize> {
+        self.0
+            .iter()
+            .find(|(threshold, _)| file_size >= *threshold)
+            .map(|(_, rate)| *rate)
+    }
+}
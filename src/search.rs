@@ -0,0 +1,129 @@
+use crate::{error::AppError, modified_secs, serveignore::Rules, AppState, FileEntry};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::{fs, path::Path};
+use tracing::{info, warn};
+
+// 单次搜索最多返回的结果数，避免根目录很大时一次返回过多数据
+const SEARCH_RESULT_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+}
+
+pub async fn handle_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> Result<Json<Vec<FileEntry>>, AppError> {
+    // 搜索结果同样会列出文件名
+    if !state.directory_listing {
+        warn!("Directory listing disabled, refusing search");
+        return Err(AppError::Forbidden);
+    }
+    let term = params.q.unwrap_or_default().trim().to_lowercase();
+    if term.is_empty() {
+        warn!("Empty search query");
+        return Err(AppError::BadRequest);
+    }
+
+    info!("Searching for: {}", term);
+    // 遍历目录是阻塞操作，放到blocking线程池里执行
+    let results = tokio::task::spawn_blocking(move || {
+        let mut results = Vec::new();
+        for mount in state.mounts.iter() {
+            let ignore = Rules::for_dir(&mount.root, &mount.root);
+            walk(
+                &state,
+                &mount.root,
+                &ignore,
+                &mount.prefix,
+                &term,
+                0,
+                &mut results,
+            );
+        }
+        results
+    })
+    .await?;
+
+    Ok(Json(results))
+}
+
+// 不跟随符号链接，既不会出现循环，也不会通过链接跑到挂载目录以外
+// 与目录列表一样跳过 `.serveignore` 匹配的条目
+fn walk(
+    state: &AppState,
+    dir: &Path,
+    ignore: &Rules,
+    relative: &str,
+    term: &str,
+    depth: usize,
+    results: &mut Vec<FileEntry>,
+) {
+    if depth > state.search_depth {
+        return;
+    }
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        if results.len() >= SEARCH_RESULT_LIMIT {
+            return;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_symlink() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !state.show_hidden && name.starts_with('.') {
+            continue;
+        }
+        if ignore.is_ignored(&entry.path(), file_type.is_dir()) {
+            continue;
+        }
+        let entry_path = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+
+        if name.to_lowercase().contains(term) {
+            let metadata = entry.metadata().ok();
+            let is_dir = file_type.is_dir();
+            results.push(FileEntry {
+                name: entry_path.clone(),
+                is_dir,
+                size: metadata.as_ref().filter(|_| !is_dir).map(|m| m.len()),
+                url: format!(
+                    "{}/{}",
+                    state.base_path,
+                    utf8_percent_encode(&entry_path, NON_ALPHANUMERIC)
+                ),
+                modified: metadata.as_ref().and_then(modified_secs),
+                // 搜索不进入符号链接
+                is_symlink: false,
+                target: None,
+            });
+        }
+
+        if file_type.is_dir() {
+            let ignore = ignore.child(&entry.path());
+            walk(
+                state,
+                &entry.path(),
+                &ignore,
+                &entry_path,
+                term,
+                depth + 1,
+                results,
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use std::{path::Path, sync::Arc};
+use tracing::warn;
+
+// 每个目录下可以放一个，语法与 `.gitignore` 相同，规则相对于所在目录
+const IGNORE_FILE: &str = ".serveignore";
+
+// 从挂载目录到某个目录沿途的 `.serveignore`，浅的在前
+#[derive(Clone, Default)]
+pub struct Rules(Vec<Arc<Gitignore>>);
+
+impl Rules {
+    // 收集root到dir（包括两端）路径上所有的 `.serveignore`
+    pub fn for_dir(root: &Path, dir: &Path) -> Rules {
+        let mut dirs: Vec<&Path> = dir
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(root))
+            .collect();
+        dirs.reverse();
+        dirs.into_iter()
+            .fold(Rules::default(), |rules, dir| rules.child(dir))
+    }
+
+    // 进入子目录时加上该目录自己的规则
+    pub fn child(&self, dir: &Path) -> Rules {
+        match load(dir) {
+            Some(gitignore) => {
+                let mut rules = self.0.clone();
+                rules.push(Arc::new(gitignore));
+                Rules(rules)
+            }
+            None => self.clone(),
+        }
+    }
+
+    // 越深的 `.serveignore` 越优先，`!pattern` 可以重新放出上层忽略的条目
+    // path必须位于最后一个规则所在目录之下
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in self.0.iter().rev() {
+            match gitignore.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+// `--strict-ignore` 时检查直接访问的路径，挂载目录本身不会被忽略
+pub fn is_ignored(root: &Path, path: &Path, is_dir: bool) -> bool {
+    match path.parent() {
+        Some(parent) if path != root && parent.starts_with(root) => {
+            Rules::for_dir(root, parent).is_ignored(path, is_dir)
+        }
+        _ => false,
+    }
+}
+
+// 个别规则写错时只跳过这些规则，其余的照常生效
+fn load(dir: &Path) -> Option<Gitignore> {
+    let file = dir.join(IGNORE_FILE);
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&file) {
+        warn!("Invalid rules in {}: {}", file.display(), e);
+    }
+    builder
+        .build()
+        .inspect_err(|e| warn!("Cannot load {}: {}", file.display(), e))
+        .ok()
+}
@@ -0,0 +1,201 @@
+use crate::{
+    prefers_json,
+    templates::{self, escape_html},
+    vary_on_accept, AppState,
+};
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::time::Duration;
+use tracing::{error, info};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(30); // 统计文件最多30秒写一次
+
+// 链接中保留路径分隔符
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/');
+
+// 每个路径完整下载成功的次数，只在内存中累计
+// 指定了 `--stats-file` 时启动时读取，之后定期和退出时写回
+pub struct DownloadStats {
+    counts: Mutex<HashMap<String, u64>>,
+    file: Option<PathBuf>,
+    dirty: AtomicBool,
+}
+
+#[derive(Serialize)]
+pub struct StatsEntry {
+    path: String,
+    downloads: u64,
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    format: Option<String>,
+}
+
+impl DownloadStats {
+    pub fn load(file: Option<PathBuf>) -> anyhow::Result<Self> {
+        let counts = match &file {
+            Some(path) if path.exists() => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read stats file {}", path.display()))?;
+                serde_json::from_str(&content)
+                    .with_context(|| format!("Invalid stats file {}", path.display()))?
+            }
+            _ => HashMap::new(),
+        };
+        Ok(Self {
+            counts: Mutex::new(counts),
+            file,
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    fn record(&self, path: &str) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        *counts.entry(path.to_string()).or_default() += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    // 响应体的最后一块交给连接后才计数，path为None（Range请求）时不计数
+    // 中途断开的连接不会读完响应体，也就不会计入
+    pub fn track<S>(
+        self: &Arc<Self>,
+        stream: S,
+        path: Option<&str>,
+        len: u64,
+    ) -> impl Stream<Item = std::io::Result<Bytes>>
+    where
+        S: Stream<Item = std::io::Result<Bytes>>,
+    {
+        let stats = self.clone();
+        let mut path = path.map(str::to_string);
+        let mut sent = 0;
+        stream.map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                sent += bytes.len() as u64;
+                if sent >= len {
+                    if let Some(path) = path.take() {
+                        stats.record(&path);
+                    }
+                }
+            }
+            chunk
+        })
+    }
+
+    // 下载次数从多到少，次数相同时按路径排序
+    fn entries(&self) -> Vec<StatsEntry> {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries: Vec<StatsEntry> = counts
+            .iter()
+            .map(|(path, &downloads)| StatsEntry {
+                path: path.clone(),
+                downloads,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.downloads.cmp(&a.downloads).then(a.path.cmp(&b.path)));
+        entries
+    }
+
+    // 先写临时文件再重命名，写到一半退出也不会损坏已有的统计
+    pub fn save(&self) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let content = {
+            let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+            serde_json::to_string_pretty(&*counts)
+        };
+        let result = content.map_err(std::io::Error::from).and_then(|content| {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, content)?;
+            std::fs::rename(&tmp, path)
+        });
+        match result {
+            Ok(()) => info!("Download stats saved to {}", path.display()),
+            Err(e) => {
+                error!("Failed to save stats file {}: {}", path.display(), e);
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+pub fn spawn_saver(stats: Arc<DownloadStats>) {
+    if stats.file.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let stats = stats.clone();
+            let _ = tokio::task::spawn_blocking(move || stats.save()).await;
+        }
+    });
+}
+
+pub async fn handle_stats(
+    State(state): State<AppState>,
+    Query(params): Query<StatsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let entries = state.stats.entries();
+    let format = params.format.as_deref();
+    if format == Some("json") || prefers_json(&headers) {
+        let response = ([(header::CACHE_CONTROL, "no-cache")], Json(entries)).into_response();
+        return vary_on_accept(response, format);
+    }
+
+    let mut rows = String::new();
+    for entry in &entries {
+        let _ = write!(
+            rows,
+            "<tr><td><a href=\"{}{}\">{}</a></td><td>{}</td></tr>",
+            state.base_path,
+            utf8_percent_encode(&entry.path, PATH_SEGMENT),
+            escape_html(&entry.path),
+            entry.downloads
+        );
+    }
+    if entries.is_empty() {
+        rows.push_str("<tr><td colspan=\"2\">还没有下载记录</td></tr>");
+    }
+    let body_html = format!(
+        r#"<table class="stats-table"><thead><tr><th>路径</th><th>下载次数</th></tr></thead><tbody>{}</tbody></table>"#,
+        rows
+    );
+    // 不是文件路径，面包屑显示为 `/ › 下载统计`
+    let page = templates::generate_document(&templates::Document {
+        current_path: "下载统计",
+        body_html: &body_html,
+        theme: state.theme,
+        base_path: &state.base_path,
+        branding: &state.branding,
+        cdn: state.cdn,
+        download: false,
+    });
+    let response = ([(header::CACHE_CONTROL, "no-cache")], Html(page)).into_response();
+    vary_on_accept(response, format)
+}
@@ -1,9 +1,193 @@
-use crate::FileEntry;
+use crate::{FileEntry, SortKey, SortOrder};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
 
-pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
-    let entries_json = serde_json::to_string(entries).unwrap_or_else(|_| "[]".to_string());
-    let current_path_json =
-        serde_json::to_string(current_path).unwrap_or_else(|_| "\"\"".to_string());
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    Auto,
+}
+
+impl Theme {
+    fn body_class(self) -> &'static str {
+        match self {
+            Theme::Light => "theme-light",
+            Theme::Dark => "theme-dark",
+            Theme::Auto => "",
+        }
+    }
+}
+
+// 内置图标字体的地址，前面再加上 `--base-path`
+pub const ICON_FONT_PATH: &str = "/static/material-icons.ttf";
+
+// 默认使用内置的Material Icons字体，离线或内网环境也能显示图标，也不会向Google发请求；
+// 正文没有Inter时直接用系统字体。`--cdn` 时改回从Google Fonts加载
+fn font_links(cdn: bool, base_path: &str) -> String {
+    if cdn {
+        return r#"<link href="https://fonts.googleapis.com/css2?family=Inter:wght@300;400;500;600&display=swap" rel="stylesheet">
+   <link href="https://fonts.googleapis.com/icon?family=Material+Icons" rel="stylesheet">"#
+            .to_string();
+    }
+    // 与Google Fonts提供的样式相同
+    format!(
+        r#"<style>
+       @font-face {{
+           font-family: 'Material Icons';
+           font-style: normal;
+           font-weight: 400;
+           font-display: block;
+           src: url({}{}) format('truetype');
+       }}
+       .material-icons {{
+           font-family: 'Material Icons';
+           font-weight: normal;
+           font-style: normal;
+           font-size: 24px;
+           line-height: 1;
+           letter-spacing: normal;
+           text-transform: none;
+           display: inline-block;
+           white-space: nowrap;
+           word-wrap: normal;
+           direction: ltr;
+           -webkit-font-feature-settings: 'liga';
+           -webkit-font-smoothing: antialiased;
+       }}
+   </style>"#,
+        base_path, ICON_FONT_PATH
+    )
+}
+
+const DEFAULT_TITLE: &str = "Swizzer's Sharing Service";
+
+// 页面标题和副标题，`--title`/`--subtitle` 未设置时使用默认的品牌
+pub struct Branding {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+}
+
+impl Branding {
+    fn heading(&self) -> String {
+        escape_html(self.title.as_deref().unwrap_or(DEFAULT_TITLE))
+    }
+
+    // 浏览器标签页上的标题，默认的品牌带一个表情
+    fn tab_title(&self) -> String {
+        match &self.title {
+            Some(title) => escape_html(title),
+            None => format!("😊 {}", DEFAULT_TITLE),
+        }
+    }
+
+    fn subtitle_html(&self) -> String {
+        match &self.subtitle {
+            Some(subtitle) => format!(r#"<p class="subtitle">{}</p>"#, escape_html(subtitle)),
+            None => String::new(),
+        }
+    }
+}
+
+// 当前目录下直接子项的统计，不递归
+#[derive(Serialize, Default)]
+pub struct DirSummary {
+    pub files: usize,
+    pub dirs: usize,
+    pub total_size: u64,
+}
+
+impl DirSummary {
+    pub fn add(&mut self, is_dir: bool, size: Option<u64>) {
+        if is_dir {
+            self.dirs += 1;
+        } else {
+            self.files += 1;
+            self.total_size += size.unwrap_or(0);
+        }
+    }
+}
+
+// 目录列表分页信息，page从1开始
+#[derive(Serialize)]
+pub struct Pagination {
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+}
+
+// 面包屑的每一级：(显示名称, 链接)，链接编码方式与文件列表中的url一致
+fn breadcrumbs(current_path: &str, base_path: &str) -> Vec<(String, String)> {
+    let mut crumbs = vec![("/".to_string(), format!("{}/", base_path))];
+    let mut prefix = String::new();
+    for part in current_path.split('/').filter(|part| !part.is_empty()) {
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        prefix.push_str(part);
+        crumbs.push((
+            part.to_string(),
+            format!(
+                "{}/{}/",
+                base_path,
+                utf8_percent_encode(&prefix, NON_ALPHANUMERIC)
+            ),
+        ));
+    }
+    crumbs
+}
+
+// 渲染目录页面需要的全部数据
+pub struct Listing<'a> {
+    pub entries: &'a [FileEntry],
+    // 目录本身没有内容（不算 `..`），与当前页是否有条目无关
+    pub is_empty: bool,
+    // 目录条目超过 `--max-listing-entries` 时只列出了前这么多项
+    pub truncated: Option<usize>,
+    pub current_path: &'a str,
+    pub sort: SortKey,
+    pub order: SortOrder,
+    pub summary: DirSummary,
+    pub pagination: Pagination,
+    pub theme: Theme,
+    pub uploads: bool,
+    // 请求的Host头拼出的绝对地址，用于复制完整链接；没有Host头时由页面自己取
+    pub base_url: Option<String>,
+    pub base_path: &'a str,
+    pub branding: &'a Branding,
+    pub cdn: bool,
+}
+
+pub fn generate_html(listing: &Listing) -> String {
+    let Listing {
+        entries,
+        is_empty,
+        truncated,
+        current_path,
+        sort,
+        order,
+        summary,
+        pagination,
+        theme,
+        uploads,
+        base_url,
+        base_path,
+        branding,
+        cdn,
+    } = listing;
+    let entries_json = script_json(entries, "[]");
+    let summary_json = script_json(summary, "{}");
+    let pagination_json = script_json(pagination, "{}");
+    let breadcrumbs_json = script_json(&breadcrumbs(current_path, base_path), "[]");
+    let base_url_json = script_json(base_url, "null");
+    let truncated_html = match truncated {
+        Some(limit) => format!(
+            r#"<div class="listing-warning"><span class="material-icons">warning</span>目录条目过多，只显示前 {} 项，排序和统计也只包含这些条目</div>"#,
+            limit
+        ),
+        None => String::new(),
+    };
     let current_path_display = if current_path.is_empty() {
         "/"
     } else {
@@ -16,10 +200,45 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
 <head>
    <meta charset="UTF-8">
    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-   <title>😊 Swizzer's Sharing Service - {}</title>
-   <link href="https://fonts.googleapis.com/css2?family=Inter:wght@300;400;500;600&display=swap" rel="stylesheet">
-   <link href="https://fonts.googleapis.com/icon?family=Material+Icons" rel="stylesheet">
+   <title>{tab_title} - {}</title>
+   {font_links}
    <style>
+       /* 浅色和深色两套配色，auto时跟随系统设置 */
+       :root {{
+           --page-bg: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+           --panel-bg: rgba(255, 255, 255, 0.95);
+           --item-bg: rgba(255, 255, 255, 0.7);
+           --item-hover-bg: rgba(255, 255, 255, 0.9);
+           --text-color: #333;
+           --text-secondary: #666;
+           --text-muted: #888;
+           --text-faint: #aaa;
+       }}
+       
+       body.theme-dark {{
+           --page-bg: linear-gradient(135deg, #1e1f3b 0%, #2d1b3d 100%);
+           --panel-bg: rgba(30, 30, 40, 0.95);
+           --item-bg: rgba(45, 45, 60, 0.7);
+           --item-hover-bg: rgba(55, 55, 75, 0.9);
+           --text-color: #e4e4ea;
+           --text-secondary: #b0b0bc;
+           --text-muted: #8c8c9a;
+           --text-faint: #6c6c7a;
+       }}
+       
+       @media (prefers-color-scheme: dark) {{
+           body:not(.theme-light) {{
+               --page-bg: linear-gradient(135deg, #1e1f3b 0%, #2d1b3d 100%);
+               --panel-bg: rgba(30, 30, 40, 0.95);
+               --item-bg: rgba(45, 45, 60, 0.7);
+               --item-hover-bg: rgba(55, 55, 75, 0.9);
+               --text-color: #e4e4ea;
+               --text-secondary: #b0b0bc;
+               --text-muted: #8c8c9a;
+               --text-faint: #6c6c7a;
+           }}
+       }}
+       
        * {{
            margin: 0;
            padding: 0;
@@ -28,9 +247,9 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        
        body {{
            font-family: 'Inter', -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
-           background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+           background: var(--page-bg);
            min-height: 100vh;
-           color: #333;
+           color: var(--text-color);
            overflow-x: hidden;
        }}
        
@@ -41,7 +260,7 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        }}
        
        .header {{
-           background: rgba(255, 255, 255, 0.95);
+           background: var(--panel-bg);
            backdrop-filter: blur(20px);
            border-radius: 16px;
            padding: 2rem;
@@ -59,18 +278,43 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            margin-bottom: 0.5rem;
        }}
        
+       .header-top {{
+           display: flex;
+           justify-content: space-between;
+           align-items: flex-start;
+       }}
+       
+       .subtitle {{
+           color: var(--text-secondary);
+           margin-bottom: 0.5rem;
+       }}
+       
+       .theme-toggle {{
+           border: none;
+           background: none;
+           color: var(--text-muted);
+           cursor: pointer;
+           padding: 0.5rem;
+           border-radius: 8px;
+       }}
+       
+       .theme-toggle:hover {{
+           background: rgba(102, 126, 234, 0.1);
+           color: #667eea;
+       }}
+       
        .breadcrumb {{
            display: flex;
            align-items: center;
            font-size: 1rem;
-           color: #666;
+           color: var(--text-secondary);
            gap: 0.5rem;
            flex-wrap: wrap;
        }}
        
        .breadcrumb .material-icons {{
            font-size: 1.2rem;
-           color: #888;
+           color: var(--text-muted);
        }}
        
        .breadcrumb-item {{
@@ -95,18 +339,68 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        }}
        
        .breadcrumb-separator {{
-           color: #ccc;
+           color: var(--text-faint);
            margin: 0 0.25rem;
            font-size: 0.8rem;
        }}
        
        .breadcrumb-current {{
-           color: #333;
+           color: var(--text-color);
            font-weight: 500;
        }}
        
+       .dir-summary {{
+           margin-top: 0.5rem;
+           font-size: 0.875rem;
+           color: var(--text-muted);
+       }}
+       
+       .listing-warning {{
+           display: flex;
+           align-items: center;
+           gap: 0.5rem;
+           margin-bottom: 1rem;
+           padding: 0.75rem 1rem;
+           border-radius: 8px;
+           background: rgba(255, 193, 7, 0.15);
+           color: var(--text-color);
+           font-size: 0.875rem;
+       }}
+       
+       .sort-bar {{
+           display: flex;
+           align-items: center;
+           gap: 0.5rem;
+           margin-top: 1rem;
+           font-size: 0.875rem;
+           color: var(--text-muted);
+       }}
+       
+       .sort-link {{
+           display: flex;
+           align-items: center;
+           color: #667eea;
+           text-decoration: none;
+           padding: 0.25rem 0.5rem;
+           border-radius: 6px;
+           transition: all 0.2s ease;
+       }}
+       
+       .sort-link:hover {{
+           background: rgba(102, 126, 234, 0.1);
+       }}
+       
+       .sort-link.active {{
+           background: rgba(102, 126, 234, 0.15);
+           font-weight: 500;
+       }}
+       
+       .sort-link .material-icons {{
+           font-size: 1rem;
+       }}
+       
        .file-grid {{
-           background: rgba(255, 255, 255, 0.95);
+           background: var(--panel-bg);
            backdrop-filter: blur(20px);
            border-radius: 16px;
            padding: 2rem;
@@ -128,7 +422,7 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            color: inherit;
            transition: all 0.3s cubic-bezier(0.4, 0, 0.2, 1);
            border: 1px solid transparent;
-           background: rgba(255, 255, 255, 0.7);
+           background: var(--item-bg);
            position: relative;
            overflow: hidden;
        }}
@@ -148,13 +442,34 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            transform: translateY(-2px);
            box-shadow: 0 8px 25px rgba(0, 0, 0, 0.15);
            border-color: rgba(102, 126, 234, 0.3);
-           background: rgba(255, 255, 255, 0.9);
+           background: var(--item-hover-bg);
        }}
        
        .file-item:hover::before {{
            left: 100%;
        }}
        
+       .file-link {{
+           display: flex;
+           align-items: center;
+           flex: 1;
+           min-width: 0;
+           color: inherit;
+           text-decoration: none;
+           border-radius: 8px;
+       }}
+       
+       /* 键盘操作时焦点要清楚可见，按钮平时隐藏，焦点进入条目时也显示出来 */
+       a:focus-visible, button:focus-visible, .drop-zone-pick:focus-visible {{
+           outline: 3px solid #667eea;
+           outline-offset: 2px;
+       }}
+       
+       .file-item:focus-within {{
+           border-color: rgba(102, 126, 234, 0.6);
+           background: var(--item-hover-bg);
+       }}
+       
        .file-icon {{
            margin-right: 1rem;
            font-size: 1.5rem;
@@ -163,6 +478,15 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            color: #667eea;
        }}
        
+       .file-thumb {{
+           margin-right: 1rem;
+           width: 2rem;
+           height: 2rem;
+           object-fit: cover;
+           border-radius: 4px;
+           flex-shrink: 0;
+       }}
+       
        .file-info {{
            flex: 1;
            display: flex;
@@ -173,17 +497,35 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        .file-name {{
            font-weight: 500;
            font-size: 1rem;
-           color: #333;
+           color: var(--text-color);
+       }}
+       
+       .link-badge {{
+           font-size: 1rem;
+           margin-left: 0.25rem;
+           vertical-align: middle;
+           color: var(--text-muted);
        }}
        
        .file-size {{
            font-size: 0.875rem;
-           color: #888;
+           color: var(--text-muted);
            font-weight: 400;
        }}
        
-       .download-btn {{
+       .file-modified {{
+           font-size: 0.8rem;
+           color: var(--text-faint);
+           margin-left: 1rem;
+       }}
+       
+       .entry-actions {{
+           display: flex;
+           gap: 0.5rem;
            margin-left: 1rem;
+       }}
+       
+       .download-btn {{
            padding: 0.5rem;
            border: none;
            background: linear-gradient(135deg, #667eea, #764ba2);
@@ -195,7 +537,7 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            transform: translateX(10px);
        }}
        
-       .file-item:hover .download-btn {{
+       .file-item:hover .download-btn, .file-item:focus-within .download-btn {{
            opacity: 1;
            transform: translateX(0);
        }}
@@ -209,10 +551,83 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            font-size: 1.2rem;
        }}
        
+       .drop-zone {{
+           display: none;
+           align-items: center;
+           justify-content: center;
+           gap: 0.5rem;
+           padding: 1rem;
+           margin-bottom: 1rem;
+           border: 2px dashed rgba(102, 126, 234, 0.3);
+           border-radius: 12px;
+           color: var(--text-muted);
+           font-size: 0.875rem;
+           transition: all 0.2s ease;
+       }}
+       
+       .drop-zone.enabled {{
+           display: flex;
+       }}
+       
+       .drop-zone.dragging {{
+           border-color: #667eea;
+           background: rgba(102, 126, 234, 0.1);
+       }}
+       
+       .drop-zone-pick {{
+           color: #667eea;
+           cursor: pointer;
+           font-weight: 500;
+       }}
+       
+       .toast {{
+           position: fixed;
+           left: 50%;
+           bottom: 2rem;
+           transform: translateX(-50%);
+           background: rgba(0, 0, 0, 0.8);
+           color: #fff;
+           padding: 0.6rem 1.2rem;
+           border-radius: 8px;
+           font-size: 0.9rem;
+           opacity: 0;
+           pointer-events: none;
+           transition: opacity 0.2s ease;
+       }}
+       
+       .toast.show {{
+           opacity: 1;
+       }}
+       
+       .pagination {{
+           display: flex;
+           justify-content: center;
+           align-items: center;
+           gap: 1rem;
+           margin-top: 1.5rem;
+           font-size: 0.875rem;
+           color: var(--text-muted);
+       }}
+       
+       .pagination:empty {{
+           display: none;
+       }}
+       
+       .page-link {{
+           color: #667eea;
+           text-decoration: none;
+           padding: 0.25rem 0.75rem;
+           border-radius: 6px;
+       }}
+       
+       .page-link:hover {{
+           background: rgba(102, 126, 234, 0.1);
+       }}
+       
        .empty-state {{
            text-align: center;
            padding: 4rem 2rem;
-           color: #888;
+           color: var(--text-muted);
        }}
        
        .empty-state .material-icons {{
@@ -234,7 +649,39 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
                font-size: 2rem;
            }}
            
-           .file-grid {{
+           .sort-bar {{
+           display: flex;
+           align-items: center;
+           gap: 0.5rem;
+           margin-top: 1rem;
+           font-size: 0.875rem;
+           color: var(--text-muted);
+       }}
+       
+       .sort-link {{
+           display: flex;
+           align-items: center;
+           color: #667eea;
+           text-decoration: none;
+           padding: 0.25rem 0.5rem;
+           border-radius: 6px;
+           transition: all 0.2s ease;
+       }}
+       
+       .sort-link:hover {{
+           background: rgba(102, 126, 234, 0.1);
+       }}
+       
+       .sort-link.active {{
+           background: rgba(102, 126, 234, 0.15);
+           font-weight: 500;
+       }}
+       
+       .sort-link .material-icons {{
+           font-size: 1rem;
+       }}
+       
+       .file-grid {{
                padding: 1rem;
            }}
            
@@ -248,12 +695,16 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
                gap: 0.5rem;
            }}
            
-           .download-btn {{
+           .entry-actions {{
                position: absolute;
                right: 1rem;
                top: 50%;
                transform: translateY(-50%);
+           }}
+           
+           .download-btn {{
                opacity: 1;
+               transform: none;
            }}
            
            .breadcrumb {{
@@ -286,25 +737,69 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        }}
    </style>
 </head>
-<body>
+<body class="{theme_class}">
    <div class="container">
        <div class="header fade-in">
-           <h1>Swizzer's Sharing Service</h1>
-           <div class="breadcrumb" id="breadcrumb">
-               <!-- 面包屑导航将通过JavaScript生成 -->
+           <div class="header-top">
+               <h1>{heading}</h1>
+               <button class="theme-toggle" id="themeToggle" onclick="toggleTheme()" title="切换主题" aria-label="切换主题">
+                   <span class="material-icons" aria-hidden="true">dark_mode</span>
+               </button>
            </div>
+           {subtitle_html}
+           <nav class="breadcrumb" id="breadcrumb" aria-label="当前路径">
+               <!-- 面包屑导航将通过JavaScript生成 -->
+           </nav>
+           <div class="dir-summary" id="dirSummary"></div>
+           <nav class="sort-bar" id="sortBar" aria-label="排序方式">
+               <!-- 排序选项将通过JavaScript生成 -->
+           </nav>
        </div>
        
-       <div class="file-grid fade-in">
-           <div class="file-list" id="fileList">
+       <main class="file-grid fade-in">
+           {truncated_html}
+           <div class="drop-zone" id="dropZone">
+               <span class="material-icons" aria-hidden="true">cloud_upload</span>
+               <span id="dropZoneText">拖放文件到这里上传，或 <label class="drop-zone-pick" tabindex="0" role="button" onkeydown="pickFiles(event)">选择文件<input type="file" id="fileInput" multiple hidden></label></span>
+           </div>
+           <div class="file-list" id="fileList" role="list" aria-label="目录内容">
                <!-- 文件列表将通过JavaScript生成 -->
            </div>
-       </div>
+           <nav class="pagination" id="pagination" aria-label="分页"></nav>
+       </main>
    </div>
+   <div class="toast" id="toast" role="status" aria-live="polite"></div>
    
    <script>
        const entries = {entries_json};
-       const currentPath = {current_path_json};
+       const isEmpty = {is_empty};
+       const breadcrumbs = {breadcrumbs_json};
+       const summary = {summary_json};
+       const pagination = {pagination_json};
+       const uploadsEnabled = {uploads};
+       const baseUrl = {base_url_json} || window.location.origin;
+       const currentSort = '{sort}';
+       const currentOrder = '{order}';
+       
+       // 用户手动切换过的主题优先于服务端默认值
+       function isDarkTheme() {{
+           if (document.body.classList.contains('theme-dark')) return true;
+           if (document.body.classList.contains('theme-light')) return false;
+           return window.matchMedia('(prefers-color-scheme: dark)').matches;
+       }}
+       
+       function applyTheme(theme) {{
+           document.body.classList.remove('theme-light', 'theme-dark');
+           document.body.classList.add('theme-' + theme);
+           document.querySelector('#themeToggle .material-icons').textContent =
+               theme === 'dark' ? 'light_mode' : 'dark_mode';
+       }}
+       
+       function toggleTheme() {{
+           const theme = isDarkTheme() ? 'light' : 'dark';
+           localStorage.setItem('theme', theme);
+           applyTheme(theme);
+       }}
        
        function formatFileSize(bytes) {{
            if (bytes === null || bytes === undefined) return '';
@@ -314,6 +809,47 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            return Math.round(bytes / Math.pow(1024, i) * 100) / 100 + ' ' + sizes[i];
        }}
        
+       function formatModified(timestamp) {{
+           if (timestamp === null || timestamp === undefined) return '';
+           return new Date(timestamp * 1000).toLocaleString();
+       }}
+       
+       function generateSummary() {{
+           document.getElementById('dirSummary').textContent =
+               `${{summary.files}} 个文件，${{summary.dirs}} 个文件夹，共 ${{formatFileSize(summary.total_size)}}`;
+       }}
+       
+       // 只有一页时不显示分页控件，翻页时保留当前排序
+       function generatePagination() {{
+           const pages = Math.ceil(pagination.total / pagination.per_page);
+           if (pages <= 1) return;
+           const link = (page, label) =>
+               `<a href="?sort=${{currentSort}}&order=${{currentOrder}}&page=${{page}}&per_page=${{pagination.per_page}}" class="page-link">${{label}}</a>`;
+           let html = '';
+           if (pagination.page > 1) html += link(pagination.page - 1, '上一页');
+           html += `<span>第 ${{pagination.page}} / ${{pages}} 页</span>`;
+           if (pagination.page < pages) html += link(pagination.page + 1, '下一页');
+           document.getElementById('pagination').innerHTML = html;
+       }}
+       
+       function generateSortBar() {{
+           const sortBar = document.getElementById('sortBar');
+           const options = [['name', '名称'], ['size', '大小'], ['modified', '修改时间']];
+           
+           // 条目已经由服务端排好序，这里只负责切换排序参数
+           sortBar.innerHTML = '<span>排序:</span>' + options.map(([key, label]) => {{
+               const active = key === currentSort;
+               const nextOrder = active && currentOrder === 'asc' ? 'desc' : 'asc';
+               const arrow = active
+                   ? `<span class="material-icons" aria-hidden="true">${{currentOrder === 'asc' ? 'arrow_upward' : 'arrow_downward'}}</span>`
+                   : '';
+               const current = active
+                   ? ` aria-current="true" aria-label="${{label}}，当前${{currentOrder === 'asc' ? '升序' : '降序'}}"`
+                   : '';
+               return `<a href="?sort=${{key}}&order=${{nextOrder}}" class="sort-link${{active ? ' active' : ''}}"${{current}}>${{label}}${{arrow}}</a>`;
+           }}).join('');
+       }}
+       
        function getFileIcon(fileName, isDir) {{
            if (fileName === '..') return 'keyboard_arrow_up';
            if (isDir) return 'folder';
@@ -329,6 +865,7 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
                'pptx': 'slideshow',
                'txt': 'text_snippet',
                'md': 'text_snippet',
+               'log': 'text_snippet',
                'zip': 'archive',
                'rar': 'archive',
                '7z': 'archive',
@@ -336,6 +873,7 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
                'jpeg': 'image',
                'png': 'image',
                'gif': 'image',
+               'webp': 'image',
                'svg': 'image',
                'mp4': 'movie',
                'avi': 'movie',
@@ -358,31 +896,34 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
            return iconMap[ext] || 'insert_drive_file';
        }}
        
+       // 服务端能生成缩略图的格式
+       function hasThumbnail(fileName) {{
+           const ext = fileName.split('.').pop().toLowerCase();
+           return ['jpg', 'jpeg', 'png', 'gif', 'webp'].includes(ext);
+       }}
+       
+       function escapeHtml(text) {{
+           return text.replace(/[&<>"']/g, c => ({{
+               '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;'
+           }})[c]);
+       }}
+       
        function generateBreadcrumb() {{
            const breadcrumb = document.getElementById('breadcrumb');
            
-           let html = '<span class="material-icons">folder</span>';
+           let html = '<span class="material-icons" aria-hidden="true">folder</span>';
            
-           if (currentPath === '' || currentPath === '/') {{
-               html += '<span class="breadcrumb-current">/</span>';
-           }} else {{
-               html += '<a href="/" class="breadcrumb-link">/</a>';
-               
-               const pathParts = currentPath.split('/').filter(part => part !== '');
-               
-               for (let i = 0; i < pathParts.length; i++) {{
-                   html += '<span class="breadcrumb-separator">›</span>';
-                   
-                   if (i === pathParts.length - 1) {{
-                       // 当前目录，不可点击
-                       html += `<span class="breadcrumb-current">${{pathParts[i]}}</span>`;
-                   }} else {{
-                       // 上级目录，可点击
-                       const targetPath = '/' + pathParts.slice(0, i + 1).join('/');
-                       html += `<a href="${{targetPath}}" class="breadcrumb-link">${{pathParts[i]}}</a>`;
-                   }}
+           // 根目录始终是第一级，最后一级是当前目录，不可点击
+           breadcrumbs.forEach(([name, url], i) => {{
+               if (i > 0) {{
+                   html += '<span class="breadcrumb-separator" aria-hidden="true">›</span>';
                }}
-           }}
+               if (i === breadcrumbs.length - 1) {{
+                   html += `<span class="breadcrumb-current" aria-current="page">${{escapeHtml(name)}}</span>`;
+               }} else {{
+                   html += `<a href="${{url}}" class="breadcrumb-link">${{escapeHtml(name)}}</a>`;
+               }}
+           }});
            
            breadcrumb.innerHTML = html;
        }}
@@ -390,15 +931,13 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
        function renderFileList() {{
            const fileList = document.getElementById('fileList');
            
-           if (entries.length === 0) {{
-               fileList.innerHTML = `
-                   <div class="empty-state">
-                       <div class="material-icons">folder_open</div>
-                       <p>此目录为空</p>
-                   </div>
-               `;
-               return;
-           }}
+           // 空的子目录里仍有 `..`，是否为空以服务端的标记为准
+           const emptyState = isEmpty ? `
+               <div class="empty-state" role="listitem">
+                   <div class="material-icons" aria-hidden="true">folder_open</div>
+                   <p>此目录为空</p>
+               </div>
+           ` : '';
            
            fileList.innerHTML = entries.map((entry, index) => {{
                const icon = getFileIcon(entry.name, entry.is_dir);
@@ -407,39 +946,589 @@ pub fn generate_html(entries: &[FileEntry], current_path: &str) -> String {
                const itemClass = isParentDir ? 'file-item parent-dir' : 'file-item';
                
                const downloadBtn = !entry.is_dir ? `
-                   <button class="download-btn" onclick="downloadFile('${{entry.url}}', event)" title="下载文件">
-                       <span class="material-icons">download</span>
+                   <button class="download-btn" onclick="downloadFile('${{entry.url}}', event)" title="下载文件" aria-label="下载文件">
+                       <span class="material-icons" aria-hidden="true">download</span>
+                   </button>
+               ` : !isParentDir ? `
+                   <button class="download-btn" onclick="downloadFile('${{entry.url}}', event, 'zip')" title="打包下载 (zip)" aria-label="打包下载 (zip)">
+                       <span class="material-icons" aria-hidden="true">folder_zip</span>
+                   </button>
+                   <button class="download-btn" onclick="downloadFile('${{entry.url}}', event, 'tar.gz')" title="打包下载 (tar.gz)" aria-label="打包下载 (tar.gz)">
+                       <span class="material-icons" aria-hidden="true">archive</span>
+                   </button>
+               ` : '';
+               const previewBtn = ['text_snippet', 'code'].includes(icon) ? `
+                   <button class="download-btn" onclick="previewFile('${{entry.url}}', event)" title="预览" aria-label="预览">
+                       <span class="material-icons" aria-hidden="true">visibility</span>
+                   </button>
+               ` : '';
+               const linkBtn = !isParentDir ? `
+                   <button class="download-btn" onclick="copyLink('${{entry.url}}', event)" title="复制链接" aria-label="复制链接">
+                       <span class="material-icons" aria-hidden="true">link</span>
+                   </button>
+               ` : '';
+               // 符号链接加上标记，悬停时显示链接指向的路径
+               const linkBadge = entry.is_symlink ? `
+                   <span class="material-icons link-badge" aria-hidden="true" title="${{escapeHtml(entry.target ? '→ ' + entry.target : '符号链接')}}">link</span>
+               ` : '';
+               const qrBtn = !isParentDir ? `
+                   <button class="download-btn" onclick="showQrCode('${{entry.url}}', event)" title="二维码" aria-label="二维码">
+                       <span class="material-icons" aria-hidden="true">qr_code</span>
+                   </button>
+               ` : '';
+               // 与上传一样只在可写时显示
+               const deleteBtn = uploadsEnabled && !isParentDir ? `
+                   <button class="download-btn" onclick="deleteEntry('${{entry.url}}', event)" title="删除" aria-label="删除">
+                       <span class="material-icons" aria-hidden="true">delete</span>
                    </button>
                ` : '';
                
+               // 读屏软件读出名称、类型和大小，图标和按钮另外朗读
+               const label = isParentDir
+                   ? '上级目录'
+                   : `${{entry.name}}，${{entry.is_dir ? '文件夹' : '文件 ' + sizeDisplay}}${{entry.is_symlink ? '，符号链接' : ''}}`;
+               
+               // 操作按钮放在链接外面，按钮不能嵌套在链接里
                return `
-                   <a href="${{entry.url}}" class="${{itemClass}}" style="animation-delay: ${{index * 0.1}}s">
-                       <span class="material-icons file-icon">${{icon}}</span>
-                       <div class="file-info">
-                           <span class="file-name">${{entry.name}}</span>
-                           <span class="file-size">${{sizeDisplay}}</span>
-                       </div>
-                       ${{downloadBtn}}
-                   </a>
+                   <div class="${{itemClass}}" role="listitem" style="animation-delay: ${{index * 0.1}}s">
+                       <a href="${{entry.url}}" class="file-link" aria-label="${{escapeHtml(label)}}">
+                           ${{!entry.is_dir && hasThumbnail(entry.name)
+                               ? `<img class="file-thumb" src="${{entry.url}}?thumb=64" loading="lazy" alt="">`
+                               : `<span class="material-icons file-icon" aria-hidden="true">${{icon}}</span>`}}
+                           <div class="file-info">
+                               <span class="file-name">${{entry.name}}${{linkBadge}}</span>
+                               <span class="file-size">${{sizeDisplay}}<span class="file-modified">${{formatModified(entry.modified)}}</span></span>
+                           </div>
+                       </a>
+                       <div class="entry-actions">${{previewBtn}}${{downloadBtn}}${{linkBtn}}${{qrBtn}}${{deleteBtn}}</div>
+                   </div>
                `;
-           }}).join('');
+           }}).join('') + emptyState;
+       }}
+       
+       // 上传到当前目录，完成后刷新列表
+       async function uploadFiles(files) {{
+           if (files.length === 0) return;
+           const text = document.getElementById('dropZoneText');
+           const form = new FormData();
+           for (const file of files) {{
+               form.append('file', file, file.name);
+           }}
+           text.textContent = `正在上传 ${{files.length}} 个文件...`;
+           try {{
+               const response = await fetch(window.location.pathname, {{ method: 'POST', body: form }});
+               if (!response.ok) throw new Error(response.status);
+               const results = await response.json();
+               const failed = results.filter(r => r.status >= 400);
+               if (failed.length > 0) {{
+                   alert('上传失败: ' + failed.map(r => `${{r.name}} (${{r.status}})`).join(', '));
+               }}
+           }} catch (e) {{
+               alert('上传失败: ' + e.message);
+           }}
+           window.location.reload();
+       }}
+       
+       function setupUpload() {{
+           if (!uploadsEnabled) return;
+           const dropZone = document.getElementById('dropZone');
+           dropZone.classList.add('enabled');
+           document.getElementById('fileInput').addEventListener('change', e => uploadFiles(e.target.files));
+           document.addEventListener('dragover', e => {{
+               e.preventDefault();
+               dropZone.classList.add('dragging');
+           }});
+           document.addEventListener('dragleave', e => {{
+               if (e.relatedTarget === null) dropZone.classList.remove('dragging');
+           }});
+           document.addEventListener('drop', e => {{
+               e.preventDefault();
+               dropZone.classList.remove('dragging');
+               uploadFiles(e.dataTransfer.files);
+           }});
+       }}
+       
+       // 目录按format打包下载，文件直接下载
+       function downloadFile(url, event, format = '1') {{
+           event.preventDefault();
+           event.stopPropagation();
+           window.location.href = url + '?download=' + format;
+       }}
+       
+       // 删除文件或空目录，成功后刷新列表
+       async function deleteEntry(url, event) {{
+           event.preventDefault();
+           event.stopPropagation();
+           const name = decodeURIComponent(url.split('/').pop());
+           if (!confirm(`确定删除 ${{name}} 吗？`)) return;
+           const response = await fetch(url, {{ method: 'DELETE' }});
+           if (response.status === 409) {{
+               alert('目录不为空，无法删除');
+           }} else if (!response.ok) {{
+               alert('删除失败: ' + response.status);
+           }}
+           window.location.reload();
+       }}
+       
+       function previewFile(url, event) {{
+           event.preventDefault();
+           event.stopPropagation();
+           window.location.href = url + '?view=1';
+       }}
+       
+       function showToast(message) {{
+           const toast = document.getElementById('toast');
+           toast.textContent = message;
+           toast.classList.add('show');
+           clearTimeout(toast.hideTimer);
+           toast.hideTimer = setTimeout(() => toast.classList.remove('show'), 1500);
        }}
        
-       function downloadFile(url, event) {{
+       // Clipboard API只在HTTPS或localhost下可用，其他情况退回execCommand
+       async function copyLink(url, event) {{
            event.preventDefault();
            event.stopPropagation();
-           window.location.href = url + '?download=1';
+           const link = baseUrl + url;
+           try {{
+               if (navigator.clipboard && window.isSecureContext) {{
+                   await navigator.clipboard.writeText(link);
+               }} else {{
+                   const input = document.createElement('textarea');
+                   input.value = link;
+                   input.style.position = 'fixed';
+                   input.style.opacity = '0';
+                   document.body.appendChild(input);
+                   input.select();
+                   const ok = document.execCommand('copy');
+                   document.body.removeChild(input);
+                   if (!ok) throw new Error('copy failed');
+               }}
+               showToast('已复制链接');
+           }} catch (e) {{
+               window.prompt('复制链接', link);
+           }}
+       }}
+       
+       function showQrCode(url, event) {{
+           event.preventDefault();
+           event.stopPropagation();
+           window.open(url + '?qr=1', '_blank');
+       }}
+       
+       // 上下方向键在条目之间移动焦点，Home/End跳到第一项和最后一项，Enter由链接本身打开
+       // 焦点在输入框或其他控件上时不处理，不影响页面原有的滚动
+       function navigateList(event) {{
+           if (event.altKey || event.ctrlKey || event.metaKey || event.shiftKey) return;
+           const links = [...document.querySelectorAll('#fileList .file-link')];
+           if (links.length === 0) return;
+           const item = document.activeElement.closest && document.activeElement.closest('#fileList .file-item');
+           const current = item ? links.indexOf(item.querySelector('.file-link')) : -1;
+           if (current < 0 && document.activeElement !== document.body) return;
+           let next;
+           switch (event.key) {{
+               case 'ArrowDown': next = current < 0 ? 0 : Math.min(current + 1, links.length - 1); break;
+               case 'ArrowUp': next = current < 0 ? 0 : Math.max(current - 1, 0); break;
+               case 'Home': next = 0; break;
+               case 'End': next = links.length - 1; break;
+               default: return;
+           }}
+           if (current < 0 && (event.key === 'Home' || event.key === 'End')) return;
+           event.preventDefault();
+           links[next].focus();
+       }}
+       
+       // “选择文件”可以用Enter或空格打开文件选择框
+       function pickFiles(event) {{
+           if (event.key !== 'Enter' && event.key !== ' ') return;
+           event.preventDefault();
+           document.getElementById('fileInput').click();
        }}
        
        document.addEventListener('DOMContentLoaded', () => {{
+           document.addEventListener('keydown', navigateList);
+           applyTheme(localStorage.getItem('theme') || (isDarkTheme() ? 'dark' : 'light'));
            generateBreadcrumb();
+           generateSummary();
+           generateSortBar();
            renderFileList();
+           generatePagination();
+           setupUpload();
        }});
    </script>
 </body>
 </html>"#,
-        current_path_display,
+        escape_html(current_path_display),
+        tab_title = branding.tab_title(),
+        font_links = font_links(*cdn, base_path),
+        heading = branding.heading(),
+        subtitle_html = branding.subtitle_html(),
         entries_json = entries_json,
-        current_path_json = current_path_json
+        is_empty = is_empty,
+        truncated_html = truncated_html,
+        breadcrumbs_json = breadcrumbs_json,
+        summary_json = summary_json,
+        pagination_json = pagination_json,
+        uploads = uploads,
+        base_url_json = base_url_json,
+        theme_class = theme.body_class(),
+        sort = sort.as_str(),
+        order = order.as_str()
+    )
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 嵌进内联script的JSON，文件名和请求头都可能带 `</script>`，转义 `<` 防止提前结束标签
+fn script_json<T: Serialize + ?Sized>(value: &T, fallback: &str) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| fallback.to_string())
+        .replace('<', "\\u003c")
+}
+
+// 单个文件的预览页面，以及下载统计这样的内置页面，样式与目录页面一致
+// body_html由调用方生成，其中的文件内容必须已经转义
+pub struct Document<'a> {
+    pub current_path: &'a str,
+    pub body_html: &'a str,
+    pub theme: Theme,
+    pub base_path: &'a str,
+    pub branding: &'a Branding,
+    pub cdn: bool,
+    // 文件预览页面带下载按钮，下载统计这类没有对应文件的页面不带
+    pub download: bool,
+}
+
+pub fn generate_document(document: &Document) -> String {
+    let Document {
+        current_path,
+        body_html,
+        theme,
+        base_path,
+        branding,
+        cdn,
+        download,
+    } = document;
+    let crumbs = breadcrumbs(current_path, base_path);
+    let mut breadcrumb_html = String::new();
+    for (i, (name, url)) in crumbs.iter().enumerate() {
+        if i > 0 {
+            breadcrumb_html.push_str(r#"<span class="breadcrumb-separator">/</span>"#);
+        }
+        if i + 1 == crumbs.len() {
+            breadcrumb_html.push_str(&format!(
+                r#"<span class="breadcrumb-current">{}</span>"#,
+                escape_html(name)
+            ));
+        } else {
+            breadcrumb_html.push_str(&format!(
+                r#"<a href="{}" class="breadcrumb-link">{}</a>"#,
+                url,
+                escape_html(name)
+            ));
+        }
+    }
+    // 面包屑的链接都以 `/` 结尾，文件的下载地址单独生成，避免多一次重定向
+    let download_html = if *download {
+        format!(
+            r#"<a href="{}/{}?download=1" title="下载文件"><span class="material-icons">download</span></a>"#,
+            base_path,
+            utf8_percent_encode(current_path.trim_matches('/'), NON_ALPHANUMERIC)
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+   <meta charset="UTF-8">
+   <meta name="viewport" content="width=device-width, initial-scale=1.0">
+   <title>{tab_title} - {title}</title>
+   {font_links}
+   <style>
+       :root {{
+           --page-bg: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
+           --panel-bg: rgba(255, 255, 255, 0.95);
+           --code-bg: #f6f8fa;
+           --text-color: #333;
+           --text-secondary: #666;
+           --text-muted: #888;
+           --text-faint: #aaa;
+       }}
+       
+       body.theme-dark {{
+           --page-bg: linear-gradient(135deg, #1e1f3b 0%, #2d1b3d 100%);
+           --panel-bg: rgba(30, 30, 40, 0.95);
+           --code-bg: #1c1c26;
+           --text-color: #e4e4ea;
+           --text-secondary: #b0b0bc;
+           --text-muted: #8c8c9a;
+           --text-faint: #6c6c7a;
+       }}
+       
+       @media (prefers-color-scheme: dark) {{
+           body:not(.theme-light) {{
+               --page-bg: linear-gradient(135deg, #1e1f3b 0%, #2d1b3d 100%);
+               --panel-bg: rgba(30, 30, 40, 0.95);
+               --code-bg: #1c1c26;
+               --text-color: #e4e4ea;
+               --text-secondary: #b0b0bc;
+               --text-muted: #8c8c9a;
+               --text-faint: #6c6c7a;
+           }}
+       }}
+       
+       * {{
+           margin: 0;
+           padding: 0;
+           box-sizing: border-box;
+       }}
+       
+       body {{
+           font-family: 'Inter', -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+           background: var(--page-bg);
+           min-height: 100vh;
+           color: var(--text-color);
+       }}
+       
+       .container {{
+           max-width: 1200px;
+           margin: 0 auto;
+           padding: 2rem;
+       }}
+       
+       .header, .document {{
+           background: var(--panel-bg);
+           border-radius: 16px;
+           padding: 2rem;
+           margin-bottom: 2rem;
+           box-shadow: 0 8px 32px rgba(0, 0, 0, 0.1);
+           border: 1px solid rgba(255, 255, 255, 0.2);
+       }}
+       
+       .header-top {{
+           display: flex;
+           justify-content: space-between;
+           align-items: center;
+       }}
+       
+       .header-actions a, .header-actions button {{
+           border: none;
+           background: none;
+           color: var(--text-muted);
+           cursor: pointer;
+           padding: 0.5rem;
+           border-radius: 8px;
+           text-decoration: none;
+       }}
+       
+       .header-actions a:hover, .header-actions button:hover {{
+           background: rgba(102, 126, 234, 0.1);
+           color: #667eea;
+       }}
+       
+       .breadcrumb {{
+           display: flex;
+           align-items: center;
+           font-size: 1rem;
+           color: var(--text-secondary);
+           gap: 0.5rem;
+           flex-wrap: wrap;
+       }}
+       
+       .breadcrumb-link {{
+           color: #667eea;
+           text-decoration: none;
+           padding: 0.25rem 0.5rem;
+           border-radius: 6px;
+           font-weight: 500;
+       }}
+       
+       .breadcrumb-separator {{
+           color: var(--text-faint);
+       }}
+       
+       .breadcrumb-current {{
+           color: var(--text-color);
+           font-weight: 500;
+       }}
+       
+       .notice {{
+           color: var(--text-secondary);
+       }}
+       
+       .notice a {{
+           color: #667eea;
+       }}
+       
+       .markdown {{
+           line-height: 1.7;
+           overflow-wrap: break-word;
+       }}
+       
+       .markdown > * + * {{
+           margin-top: 1rem;
+       }}
+       
+       .markdown h1, .markdown h2, .markdown h3, .markdown h4 {{
+           font-weight: 600;
+           line-height: 1.3;
+       }}
+       
+       .markdown h1, .markdown h2 {{
+           padding-bottom: 0.3rem;
+           border-bottom: 1px solid rgba(128, 128, 128, 0.2);
+       }}
+       
+       .markdown ul, .markdown ol {{
+           padding-left: 2rem;
+       }}
+       
+       .markdown a {{
+           color: #667eea;
+       }}
+       
+       .markdown code {{
+           font-family: 'SFMono-Regular', Consolas, 'Liberation Mono', Menlo, monospace;
+           font-size: 0.875em;
+           background: var(--code-bg);
+           padding: 0.15rem 0.35rem;
+           border-radius: 4px;
+       }}
+       
+       .markdown pre {{
+           background: var(--code-bg);
+           padding: 1rem;
+           border-radius: 8px;
+           overflow-x: auto;
+       }}
+       
+       .markdown pre code {{
+           padding: 0;
+           background: none;
+       }}
+       
+       .markdown blockquote {{
+           padding-left: 1rem;
+           border-left: 4px solid rgba(102, 126, 234, 0.4);
+           color: var(--text-secondary);
+       }}
+       
+       .markdown table {{
+           border-collapse: collapse;
+       }}
+       
+       .markdown th, .markdown td {{
+           padding: 0.4rem 0.8rem;
+           border: 1px solid rgba(128, 128, 128, 0.3);
+       }}
+       
+       .markdown img {{
+           max-width: 100%;
+       }}
+       
+       .stats-table {{
+           width: 100%;
+           border-collapse: collapse;
+       }}
+       
+       .stats-table th, .stats-table td {{
+           padding: 0.5rem 1rem;
+           border-bottom: 1px solid rgba(128, 128, 128, 0.2);
+           text-align: left;
+       }}
+       
+       .stats-table th:last-child, .stats-table td:last-child {{
+           text-align: right;
+       }}
+       
+       .stats-table a {{
+           color: #667eea;
+           text-decoration: none;
+       }}
+       
+       /* 用CSS计数器显示行号，复制时不会带上行号 */
+       .text-view {{
+           font-family: 'SFMono-Regular', Consolas, 'Liberation Mono', Menlo, monospace;
+           font-size: 0.875rem;
+           line-height: 1.5;
+           background: var(--code-bg);
+           border-radius: 8px;
+           padding: 1rem 0;
+           overflow-x: auto;
+           counter-reset: line;
+       }}
+       
+       .text-view .line {{
+           display: block;
+           padding: 0 1rem;
+           white-space: pre;
+       }}
+       
+       .text-view .line::before {{
+           counter-increment: line;
+           content: counter(line);
+           display: inline-block;
+           width: 3rem;
+           margin-right: 1rem;
+           text-align: right;
+           color: var(--text-faint);
+           user-select: none;
+       }}
+   </style>
+</head>
+<body class="{theme_class}">
+   <div class="container">
+       <div class="header">
+           <div class="header-top">
+               <div class="breadcrumb">{breadcrumb_html}</div>
+               <div class="header-actions">
+                   {download_html}
+                   <button id="themeToggle" onclick="toggleTheme()" title="切换主题">
+                       <span class="material-icons">dark_mode</span>
+                   </button>
+               </div>
+           </div>
+       </div>
+       <div class="document">{body_html}</div>
+   </div>
+   
+   <script>
+       function isDarkTheme() {{
+           if (document.body.classList.contains('theme-dark')) return true;
+           if (document.body.classList.contains('theme-light')) return false;
+           return window.matchMedia('(prefers-color-scheme: dark)').matches;
+       }}
+       
+       function applyTheme(theme) {{
+           document.body.classList.remove('theme-light', 'theme-dark');
+           document.body.classList.add('theme-' + theme);
+           document.querySelector('#themeToggle .material-icons').textContent =
+               theme === 'dark' ? 'light_mode' : 'dark_mode';
+       }}
+       
+       function toggleTheme() {{
+           const theme = isDarkTheme() ? 'light' : 'dark';
+           localStorage.setItem('theme', theme);
+           applyTheme(theme);
+       }}
+       
+       applyTheme(localStorage.getItem('theme') || (isDarkTheme() ? 'dark' : 'light'));
+   </script>
+</body>
+</html>"#,
+        tab_title = branding.tab_title(),
+        font_links = font_links(*cdn, base_path),
+        title = escape_html(current_path),
+        breadcrumb_html = breadcrumb_html,
+        download_html = download_html,
+        body_html = body_html,
+        theme_class = theme.body_class(),
     )
 }
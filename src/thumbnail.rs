@@ -0,0 +1,105 @@
+use crate::{
+    error::{AppError, PathContext as _},
+    AppState,
+};
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use image::{codecs::jpeg::JpegEncoder, ImageFormat, ImageReader, Limits};
+use moka::future::Cache;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::{info, warn};
+
+const MIN_THUMB_SIZE: u32 = 16;
+const MAX_THUMB_SIZE: u32 = 512;
+const THUMB_CACHE_SIZE: u64 = 32 * 1024 * 1024; // 缩略图缓存总共32MB
+const THUMB_QUALITY: u8 = 80;
+// 解码前先检查图片尺寸和内存占用，防止很小的文件解压出巨大的图片
+const MAX_SOURCE_DIMENSION: u32 = 12_000;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+// 键中带上修改时间，文件更新后自然不会命中旧的缩略图
+pub type ThumbCache = Cache<(PathBuf, u32, SystemTime), Bytes>;
+
+pub fn new_cache() -> ThumbCache {
+    Cache::builder()
+        .weigher(|_, data: &Bytes| data.len() as u32)
+        .max_capacity(THUMB_CACHE_SIZE)
+        .build()
+}
+
+// `?thumb=128` 返回最长边不超过128像素的JPEG缩略图
+pub async fn thumbnail_response(
+    file_path: &Path,
+    size: &str,
+    state: &AppState,
+) -> Result<Response, AppError> {
+    let size = size
+        .parse::<u32>()
+        .ok()
+        .filter(|size| (MIN_THUMB_SIZE..=MAX_THUMB_SIZE).contains(size))
+        .ok_or_else(|| {
+            warn!("Invalid thumbnail size: {}", size);
+            AppError::BadRequest
+        })?;
+    if !ImageFormat::from_path(file_path).is_ok_and(|format| {
+        matches!(
+            format,
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+        )
+    }) {
+        warn!("Thumbnail requested for non-image: {}", file_path.display());
+        return Err(AppError::UnsupportedMediaType);
+    }
+    let modified = tokio::fs::metadata(file_path)
+        .await
+        .and_then(|m| m.modified())
+        .at(file_path)?;
+
+    let key = (file_path.to_path_buf(), size, modified);
+    let data = match state.thumb_cache.get(&key).await {
+        Some(data) => data,
+        None => {
+            info!("Generating {}px thumbnail: {}", size, file_path.display());
+            let path = file_path.to_path_buf();
+            // 解码和缩放都很耗CPU，放到blocking线程池里执行
+            let data = tokio::task::spawn_blocking(move || generate(&path, size))
+                .await?
+                .map_err(|e| {
+                    warn!("Cannot generate thumbnail {}: {}", file_path.display(), e);
+                    AppError::UnsupportedMediaType
+                })?;
+            state.thumb_cache.insert(key, data.clone()).await;
+            data
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("image/jpeg")),
+            (header::CACHE_CONTROL, state.cache_control.clone()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+fn generate(path: &Path, size: u32) -> image::ImageResult<Bytes> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(limits);
+    // JPEG不支持透明通道，统一转成RGB
+    let thumbnail = reader.decode()?.thumbnail(size, size).to_rgb8();
+    let mut output = Vec::new();
+    JpegEncoder::new_with_quality(&mut output, THUMB_QUALITY).encode_image(&thumbnail)?;
+    Ok(Bytes::from(output))
+}
@@ -0,0 +1,280 @@
+use crate::{
+    error::{AppError, PathContext as _},
+    upload::{exceeds_limit, resolve_target, temp_path_for},
+    AppState, BUILTIN_PREFIX,
+};
+use axum::{
+    body::BodyDataStream,
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    RequestExt,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::StreamExt;
+use moka::{future::Cache, notification::RemovalCause};
+use std::{
+    path::{Path as StdPath, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use tracing::{info, warn};
+
+// 只实现tus 1.0.0的核心协议和creation扩展
+// 不提供OPTIONS的服务器能力查询，配置了 `--cors-origin` 时OPTIONS由CORS层应答预检
+const TUS_VERSION: &str = "1.0.0";
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+
+const MAX_PENDING_UPLOADS: u64 = 10_000;
+// 超过一天没有继续的上传视为放弃，删除临时文件
+const UPLOAD_IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+// 进行中的上传，已收到的数据在目标旁边的临时文件里，文件大小就是当前偏移量
+pub struct Upload {
+    root: PathBuf,
+    relative: String,
+    temp: PathBuf,
+    length: u64,
+    // 同一个上传的PATCH依次处理
+    lock: Mutex<()>,
+}
+
+pub type Uploads = Cache<String, Arc<Upload>>;
+
+pub fn new_uploads() -> Uploads {
+    Cache::builder()
+        .max_capacity(MAX_PENDING_UPLOADS)
+        .time_to_idle(UPLOAD_IDLE_TIMEOUT)
+        .eviction_listener(|id, upload: Arc<Upload>, cause| {
+            // 完成时由handler主动移除，其余情况（过期、超出容量）说明上传被放弃
+            if cause != RemovalCause::Explicit {
+                info!("Discarding abandoned upload {}", id);
+                let _ = std::fs::remove_file(&upload.temp);
+            }
+        })
+        .build()
+}
+
+// POST /_sfs/tus 创建上传，目标路径来自Upload-Metadata中的 `path`，没有时用 `filename` 放到根目录
+pub async fn handle_create(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_version(&headers)?;
+    let length = header_u64(&headers, &UPLOAD_LENGTH).ok_or_else(|| {
+        warn!("Upload-Length missing or invalid");
+        AppError::BadRequest
+    })?;
+    if state.max_upload_size > 0 && length > state.max_upload_size {
+        warn!("Upload of {} bytes exceeds size limit", length);
+        return Err(AppError::PayloadTooLarge);
+    }
+    let metadata = headers.get(UPLOAD_METADATA);
+    let path = metadata_value(metadata, "path")
+        .or_else(|| {
+            // 与网页上传一样只保留文件名
+            metadata_value(metadata, "filename").and_then(|name| {
+                StdPath::new(&name)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+            })
+        })
+        .ok_or_else(|| {
+            warn!("Upload-Metadata has no path or filename");
+            AppError::BadRequest
+        })?;
+    let Some((mount, relative)) = state.resolve(&path) else {
+        warn!("No mount for upload path: {}", path);
+        return Err(AppError::NotFound);
+    };
+    let target = resolve_target(&mount.root, relative, state.follow_symlinks).await?;
+    if fs::metadata(&target).await.is_ok_and(|m| m.is_dir()) {
+        warn!("Refusing to overwrite directory: {}", target.display());
+        return Err(AppError::Conflict);
+    }
+
+    let temp = temp_path_for(&target);
+    fs::File::create(&temp).await.at(&temp)?;
+    let upload = Arc::new(Upload {
+        root: mount.root.clone(),
+        relative: relative.to_string(),
+        temp,
+        length,
+        lock: Mutex::new(()),
+    });
+    let id = new_id();
+    info!(
+        "Upload {} created for {} ({} bytes)",
+        id,
+        target.display(),
+        length
+    );
+    if length == 0 {
+        complete(&state, &id, &upload).await?;
+    } else {
+        state.tus_uploads.insert(id.clone(), upload).await;
+    }
+
+    let mut headers = tus_headers();
+    let location = format!("{}{}/tus/{}", state.base_path, BUILTIN_PREFIX, id);
+    headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&location).map_err(|_| AppError::Internal)?,
+    );
+    Ok((StatusCode::CREATED, headers).into_response())
+}
+
+// HEAD /_sfs/tus/{id} 查询已经收到的字节数，断线后从这里继续
+pub async fn handle_head(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_version(&headers)?;
+    let upload = find(&state, &id).await?;
+    let offset = fs::metadata(&upload.temp).await.at(&upload.temp)?.len();
+    let mut headers = tus_headers();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+    headers.insert(UPLOAD_LENGTH, HeaderValue::from(upload.length));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok((StatusCode::OK, headers).into_response())
+}
+
+// PATCH /_sfs/tus/{id} 从Upload-Offset处追加数据，收齐Upload-Length后移动到目标位置
+pub async fn handle_patch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    request: Request,
+) -> Result<Response, AppError> {
+    check_version(request.headers())?;
+    if request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_none_or(|v| v != "application/offset+octet-stream")
+    {
+        warn!("PATCH for upload {} without offset+octet-stream body", id);
+        return Err(AppError::UnsupportedMediaType);
+    }
+    let offset = header_u64(request.headers(), &UPLOAD_OFFSET).ok_or_else(|| {
+        warn!("Upload-Offset missing or invalid");
+        AppError::BadRequest
+    })?;
+    let upload = find(&state, &id).await?;
+    let _guard = upload.lock.lock().await;
+    let current = fs::metadata(&upload.temp).await.at(&upload.temp)?.len();
+    if offset != current {
+        warn!(
+            "Upload {} offset mismatch: got {}, expected {}",
+            id, offset, current
+        );
+        return Err(AppError::Conflict);
+    }
+
+    // Body提取器不受DefaultBodyLimit约束，这里显式加上限制
+    let body = request.with_limited_body().into_body();
+    append(&upload, current, body.into_data_stream()).await?;
+    let offset = fs::metadata(&upload.temp).await.at(&upload.temp)?.len();
+    if offset == upload.length {
+        complete(&state, &id, &upload).await?;
+    }
+    let mut headers = tus_headers();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+    Ok((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+// 客户端中途断开时已经收到的部分照样写入磁盘，下次从这里继续
+async fn append(upload: &Upload, offset: u64, mut stream: BodyDataStream) -> Result<(), AppError> {
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(&upload.temp)
+        .await
+        .at(&upload.temp)?;
+    let mut written = offset;
+    let result = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| {
+                if exceeds_limit(&e) {
+                    warn!("Upload chunk exceeds size limit: {}", upload.temp.display());
+                    AppError::PayloadTooLarge
+                } else {
+                    warn!("Upload interrupted at {} bytes: {}", written, e);
+                    AppError::BadRequest
+                }
+            })?;
+            if written + chunk.len() as u64 > upload.length {
+                warn!(
+                    "Upload exceeds its Upload-Length: {}",
+                    upload.temp.display()
+                );
+                return Err(AppError::PayloadTooLarge);
+            }
+            file.write_all(&chunk).await.at(&upload.temp)?;
+            written += chunk.len() as u64;
+        }
+        Ok(())
+    }
+    .await;
+    file.flush().await.at(&upload.temp)?;
+    file.sync_data().await.at(&upload.temp)?;
+    result
+}
+
+// 创建之后目录可能发生变化，移动前重新检查目标路径
+async fn complete(state: &AppState, id: &str, upload: &Upload) -> Result<(), AppError> {
+    let target = resolve_target(&upload.root, &upload.relative, state.follow_symlinks).await?;
+    if fs::metadata(&target).await.is_ok_and(|m| m.is_dir()) {
+        warn!("Refusing to overwrite directory: {}", target.display());
+        return Err(AppError::Conflict);
+    }
+    fs::rename(&upload.temp, &target).await.at(&target)?;
+    state.tus_uploads.invalidate(id).await;
+    info!("File uploaded: {}", target.display());
+    Ok(())
+}
+
+async fn find(state: &AppState, id: &str) -> Result<Arc<Upload>, AppError> {
+    state.tus_uploads.get(id).await.ok_or_else(|| {
+        warn!("Unknown upload: {}", id);
+        AppError::NotFound
+    })
+}
+
+fn tus_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    headers
+}
+
+fn check_version(headers: &HeaderMap) -> Result<(), AppError> {
+    match headers.get(TUS_RESUMABLE) {
+        Some(version) if version == TUS_VERSION => Ok(()),
+        version => {
+            warn!("Unsupported Tus-Resumable: {:?}", version);
+            Err(AppError::PreconditionFailed)
+        }
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+// Upload-Metadata格式为 `key base64,key base64`
+fn metadata_value(header: Option<&HeaderValue>, key: &str) -> Option<String> {
+    header?.to_str().ok()?.split(',').find_map(|pair| {
+        let (name, value) = pair.trim().split_once(' ')?;
+        if name != key {
+            return None;
+        }
+        String::from_utf8(STANDARD.decode(value.trim()).ok()?).ok()
+    })
+}
+
+// 地址就是继续上传的凭据，用系统随机数生成的UUID，不能被猜出来
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
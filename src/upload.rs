@@ -0,0 +1,251 @@
+use crate::{
+    error::{AppError, PathContext as _},
+    AppState,
+};
+use axum::{
+    extract::{Multipart, Path, Request, State},
+    http::StatusCode,
+    Json, RequestExt,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use http_body_util::LengthLimitError;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::{
+    path::{Component, Path as StdPath, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{info, warn};
+
+pub async fn handle_upload(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    request: Request,
+) -> Result<StatusCode, AppError> {
+    let decoded_path = percent_decode_str(&path).decode_utf8().map_err(|_| {
+        warn!("Invalid UTF-8 in path: {}", path);
+        AppError::BadRequest
+    })?;
+    let Some((mount, relative)) = state.resolve(&decoded_path) else {
+        warn!("No mount for upload path: {}", decoded_path);
+        return Err(AppError::NotFound);
+    };
+    let target = resolve_target(&mount.root, relative, state.follow_symlinks).await?;
+    // Body提取器不受DefaultBodyLimit约束，这里显式加上限制
+    let body = request.with_limited_body().into_body();
+    save_upload(&target, body.into_data_stream()).await
+}
+
+#[derive(Serialize)]
+pub struct UploadResult {
+    name: String,
+    status: u16,
+}
+
+// 网页上传：把multipart中的每个文件保存到目标目录，逐个返回结果
+pub async fn handle_form_upload(
+    State(state): State<AppState>,
+    path: Option<Path<String>>,
+    mut multipart: Multipart,
+) -> Result<Json<Vec<UploadResult>>, AppError> {
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    let decoded_path = percent_decode_str(&path).decode_utf8().map_err(|_| {
+        warn!("Invalid UTF-8 in path: {}", path);
+        AppError::BadRequest
+    })?;
+    let Some((mount, relative)) = state.resolve(&decoded_path) else {
+        warn!("No mount for upload path: {}", decoded_path);
+        return Err(AppError::NotFound);
+    };
+
+    let mut results = Vec::new();
+    // 超过大小限制时status()是413，其余格式错误是400
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        warn!("Malformed multipart upload: {}", e);
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            AppError::PayloadTooLarge
+        } else {
+            AppError::BadRequest
+        }
+    })? {
+        let Some(file_name) = field.file_name().map(|n| n.to_string()) else {
+            continue;
+        };
+        // 浏览器可能带上客户端的路径，只保留最后的文件名
+        let name = StdPath::new(&file_name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let status = if name.is_empty() {
+            warn!("Rejecting upload with invalid file name: {:?}", file_name);
+            StatusCode::BAD_REQUEST
+        } else {
+            let relative_file = match relative.trim_matches('/') {
+                "" => name.clone(),
+                dir => format!("{}/{}", dir, name),
+            };
+            let saved =
+                match resolve_target(&mount.root, &relative_file, state.follow_symlinks).await {
+                    Ok(target) => save_upload(&target, field).await,
+                    Err(err) => Err(err),
+                };
+            // 单个文件失败不影响其他文件，错误只体现在该文件的结果里
+            saved.unwrap_or_else(|err| {
+                err.log();
+                err.status()
+            })
+        };
+        results.push(UploadResult {
+            name: if name.is_empty() { file_name } else { name },
+            status: status.as_u16(),
+        });
+    }
+    Ok(Json(results))
+}
+
+// 先写入同目录下的临时文件，完成后再rename，避免读到写了一半的文件
+async fn save_upload<S, E>(target: &StdPath, stream: S) -> Result<StatusCode, AppError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let existed = match fs::metadata(target).await {
+        Ok(m) if m.is_dir() => {
+            warn!("Refusing to overwrite directory: {}", target.display());
+            return Err(AppError::Conflict);
+        }
+        Ok(_) => true,
+        Err(_) => false,
+    };
+
+    let tmp_path = temp_path_for(target);
+    if let Err(e) = write_stream(&tmp_path, stream).await {
+        let _ = fs::remove_file(&tmp_path).await;
+        if e.get_ref().is_some_and(|inner| exceeds_limit(inner)) {
+            warn!("Upload exceeds size limit: {}", target.display());
+            return Err(AppError::PayloadTooLarge);
+        }
+        return Err(e).at(&tmp_path);
+    }
+    fs::rename(&tmp_path, target).await.at(target)?;
+
+    info!("File uploaded: {}", target.display());
+    Ok(if existed {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CREATED
+    })
+}
+
+// 解析上传目标路径，目标文件可能还不存在，所以不能直接canonicalize
+// 先拒绝 `..` 等分量，再对父目录做canonicalize检查，防止借助符号链接逃出root_dir
+pub async fn resolve_target(
+    root_dir: &StdPath,
+    decoded_path: &str,
+    follow_symlinks: bool,
+) -> Result<PathBuf, AppError> {
+    let mut relative = PathBuf::new();
+    for component in StdPath::new(decoded_path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => {
+                warn!("Directory traversal attempt blocked: {}", decoded_path);
+                return Err(AppError::Forbidden);
+            }
+        }
+    }
+    let Some(file_name) = relative.file_name().map(|n| n.to_owned()) else {
+        return Err(AppError::BadRequest);
+    };
+
+    let parent = root_dir.join(relative.parent().unwrap_or(StdPath::new("")));
+    // 创建前后都检查一次，已存在的父目录可能是指向外部的符号链接
+    ensure_within_root(root_dir, &parent).await?;
+    fs::create_dir_all(&parent).await.at(&parent)?;
+    let canonical_parent = fs::canonicalize(&parent).await.at(&parent)?;
+    if !canonical_parent.starts_with(root_dir) {
+        warn!("Directory traversal attempt blocked: {}", decoded_path);
+        return Err(AppError::Forbidden);
+    }
+    // relative里只有普通分量，canonicalize后路径变了说明经过了符号链接
+    if !follow_symlinks && canonical_parent != parent {
+        warn!("Refusing to follow symlink: {}", decoded_path);
+        return Err(AppError::Forbidden);
+    }
+
+    let target = canonical_parent.join(file_name);
+    if !follow_symlinks
+        && fs::symlink_metadata(&target)
+            .await
+            .is_ok_and(|m| m.is_symlink())
+    {
+        warn!("Refusing to follow symlink: {}", decoded_path);
+        return Err(AppError::Forbidden);
+    }
+    if let Ok(canonical) = fs::canonicalize(&target).await {
+        if !canonical.starts_with(root_dir) {
+            warn!("Directory traversal attempt blocked: {}", decoded_path);
+            return Err(AppError::Forbidden);
+        }
+    }
+    Ok(target)
+}
+
+// 找到最近的已存在祖先目录并检查其是否位于root_dir内
+async fn ensure_within_root(root_dir: &StdPath, path: &StdPath) -> Result<(), AppError> {
+    let mut ancestor = path;
+    loop {
+        if let Ok(canonical) = fs::canonicalize(ancestor).await {
+            if canonical.starts_with(root_dir) {
+                return Ok(());
+            }
+            warn!("Directory traversal attempt blocked: {}", path.display());
+            return Err(AppError::Forbidden);
+        }
+        match ancestor.parent() {
+            Some(parent) => ancestor = parent,
+            None => return Err(AppError::Forbidden),
+        }
+    }
+}
+
+pub fn temp_path_for(target: &StdPath) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.upload-{}", name, nanos))
+}
+
+// 请求体超过DefaultBodyLimit时，LengthLimitError被包在axum和multer的错误里面
+pub fn exceeds_limit(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if e.is::<LengthLimitError>() {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+async fn write_stream<S, E>(tmp_path: &StdPath, mut stream: S) -> std::io::Result<()>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut file = fs::File::create(tmp_path).await?;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    file.sync_all().await
+}
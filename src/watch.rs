@@ -0,0 +1,58 @@
+use crate::{mount::Mount, CachedFile};
+use moka::future::Cache;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{collections::HashSet, path::PathBuf};
+use tokio::{sync::mpsc, time::Duration};
+use tracing::{debug, warn};
+
+// 收到第一个事件后再等一会儿，把短时间内的连续写入合并成一次失效
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// 监听所有挂载目录，文件被外部修改或删除时主动让缓存失效
+// 返回的watcher需要一直持有，drop后监听就停止了
+pub fn spawn(
+    mounts: &[Mount],
+    cache: Cache<PathBuf, CachedFile>,
+) -> anyhow::Result<RecommendedWatcher> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("File watcher error: {}", e),
+        })?;
+    for mount in mounts {
+        watcher.watch(&mount.root, RecursiveMode::Recursive)?;
+    }
+    tokio::spawn(invalidate_changed(rx, cache));
+    Ok(watcher)
+}
+
+async fn invalidate_changed(
+    mut rx: mpsc::UnboundedReceiver<PathBuf>,
+    cache: Cache<PathBuf, CachedFile>,
+) {
+    while let Some(path) = rx.recv().await {
+        let mut changed = HashSet::from([path]);
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(path) = rx.try_recv() {
+            changed.insert(path);
+        }
+        for path in changed {
+            debug!("Invalidating cache for changed path: {}", path.display());
+            cache.invalidate(&path).await;
+            // 目录被删除或改名时，其下缓存的文件也一起失效
+            if !path.is_file() {
+                let prefix = path.clone();
+                if let Err(e) = cache.invalidate_entries_if(move |key, _| key.starts_with(&prefix))
+                {
+                    warn!("Failed to invalidate cache under {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+#!/bin/bash
+# 限速测试：7MB文件按2MB/s限速，下载耗时应约为3秒，且内容完整
+# 分档限速：同样的参数下，5MB文件不命中6M档位，按默认的不限速传输
+# 限速小于读取缓冲区：超过1GB的文件每块2MB，按1MB/s限速时平均速度也不能超过1MB/s
+# 需要先 cargo build
+
+set -e
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_DIR="$(dirname "$SCRIPT_DIR")"
+BINARY_PATH="${BINARY_PATH:-$PROJECT_DIR/target/debug/http-file-server}"
+SERVER_PORT="${SERVER_PORT:-8765}"
+
+WORK_DIR="$(mktemp -d)"
+trap 'kill $SERVER_PID 2>/dev/null; rm -rf "$WORK_DIR"' EXIT
+
+# 大于4MB缓存上限才会走流式传输分支
+mkdir "$WORK_DIR/root"
+head -c $((7 * 1024 * 1024)) /dev/urandom > "$WORK_DIR/root/test.bin"
+head -c $((5 * 1024 * 1024)) /dev/urandom > "$WORK_DIR/root/small.bin"
+# 稀疏文件，不占用磁盘空间
+truncate -s 1100M "$WORK_DIR/root/huge.bin"
+
+"$BINARY_PATH" -p $SERVER_PORT -b 127.0.0.1 --rate-limit 0 --tiered-rate 6M:2M \
+    "$WORK_DIR/root" > /dev/null 2>&1 &
+SERVER_PID=$!
+sleep 1
+
+result=$(curl -s -o "$WORK_DIR/out.bin" -w "%{size_download} %{time_total}" \
+    "http://127.0.0.1:$SERVER_PORT/test.bin")
+bytes=$(echo "$result" | cut -d' ' -f1)
+elapsed=$(echo "$result" | cut -d' ' -f2)
+
+# 每秒一个窗口，第一个窗口不需要等待：2+2+2+1MB，约3秒
+echo "下载字节数: $bytes, 耗时: ${elapsed}s (预期约 3s)"
+
+if ! cmp -s "$WORK_DIR/out.bin" "$WORK_DIR/root/test.bin"; then
+    echo "❌ 下载内容与源文件不一致"
+    exit 1
+fi
+
+if ! awk -v t="$elapsed" 'BEGIN { exit !(t >= 2.5 && t <= 4.0) }'; then
+    echo "❌ 限速耗时不符合预期"
+    exit 1
+fi
+
+small_elapsed=$(curl -s -o /dev/null -w "%{time_total}" "http://127.0.0.1:$SERVER_PORT/small.bin")
+echo "未命中档位的5MB文件耗时: ${small_elapsed}s (预期远小于 1s)"
+
+if ! awk -v t="$small_elapsed" 'BEGIN { exit !(t < 1.0) }'; then
+    echo "❌ 未命中档位的文件不应被限速"
+    exit 1
+fi
+
+kill $SERVER_PID
+wait $SERVER_PID 2>/dev/null || true
+
+"$BINARY_PATH" -p $SERVER_PORT -b 127.0.0.1 --rate-limit 1M "$WORK_DIR/root" > /dev/null 2>&1 &
+SERVER_PID=$!
+sleep 1
+
+# 只下载5秒：第一块不需要等待，之后每2MB等2秒，约6MB；超出的额度被丢弃时会达到10MB
+huge_bytes=$(curl -s -o /dev/null --max-time 5 -w "%{size_download}" \
+    "http://127.0.0.1:$SERVER_PORT/huge.bin" || true)
+echo "1MB/s限速下5秒内下载字节数: $huge_bytes (预期不超过 7MB)"
+
+if ! awk -v b="$huge_bytes" 'BEGIN { exit !(b >= 2 * 1048576 && b <= 7 * 1048576) }'; then
+    echo "❌ 限速小于缓冲区时没有生效"
+    exit 1
+fi
+
+echo "✅ 限速测试通过"
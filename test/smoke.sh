@@ -0,0 +1,1404 @@
+#!/bin/bash
+# HTTP行为冒烟测试，逐项检查响应状态码和响应头
+# 需要先 cargo build
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_DIR="$(dirname "$SCRIPT_DIR")"
+BINARY_PATH="${BINARY_PATH:-$PROJECT_DIR/target/debug/http-file-server}"
+SERVER_PORT="${SERVER_PORT:-8766}"
+BASE_URL="http://127.0.0.1:$SERVER_PORT"
+
+WORK_DIR="$(mktemp -d)"
+ROOT="$WORK_DIR/root"
+mkdir "$ROOT"
+FAILED=0
+
+cleanup() {
+    kill $SERVER_PID 2>/dev/null
+    rm -rf "$WORK_DIR"
+}
+trap cleanup EXIT
+
+start_server() {
+    "$BINARY_PATH" -p $SERVER_PORT -b 127.0.0.1 "$@" > "$WORK_DIR/server.log" 2>&1 &
+    SERVER_PID=$!
+    sleep 1
+}
+
+stop_server() {
+    kill $SERVER_PID 2>/dev/null
+    wait $SERVER_PID 2>/dev/null
+}
+
+# check 描述 实际值 期望值
+check() {
+    if [ "$2" == "$3" ]; then
+        echo "✅ $1"
+    else
+        echo "❌ $1"
+        echo "   期望: $3"
+        echo "   实际: $2"
+        FAILED=1
+    fi
+}
+
+# header 路径 响应头名 [curl参数...]
+header() {
+    curl -s -g -D - -o /dev/null "${@:3}" "$BASE_URL$1" | grep -i "^$2:" | cut -d' ' -f2- | tr -d '\r'
+}
+
+# status 路径 [curl参数...]
+status() {
+    curl -s -g -o /dev/null -w "%{http_code}" "${@:2}" "$BASE_URL$1"
+}
+
+# ---------------------------------------------------------------
+# Content-Disposition 中的非ASCII文件名
+# ---------------------------------------------------------------
+echo 'hello' > "$ROOT/a \"b\" 中文.txt"
+
+start_server "$ROOT"
+
+check "非ASCII文件名使用RFC 5987编码" \
+    "$(header '/a%20%22b%22%20%E4%B8%AD%E6%96%87.txt?download=1' content-disposition)" \
+    "attachment; filename=\"a _b_ __.txt\"; filename*=UTF-8''a%20%22b%22%20%E4%B8%AD%E6%96%87.txt"
+check "预览时inline也带上文件名" \
+    "$(header '/a%20%22b%22%20%E4%B8%AD%E6%96%87.txt' content-disposition)" \
+    "inline; filename=\"a _b_ __.txt\"; filename*=UTF-8''a%20%22b%22%20%E4%B8%AD%E6%96%87.txt"
+
+stop_server
+
+# ---------------------------------------------------------------
+# 符号链接：默认不跟随，--follow-symlinks 时只允许指向根目录内部
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/inner" "$WORK_DIR/outside"
+echo 'inside' > "$ROOT/inner/target.txt"
+echo 'secret' > "$WORK_DIR/outside/secret.txt"
+ln -s inner/target.txt "$ROOT/link-in.txt"
+ln -s inner "$ROOT/link-dir"
+ln -s "$WORK_DIR/outside/secret.txt" "$ROOT/link-out.txt"
+
+start_server "$ROOT"
+
+check "默认拒绝根目录内的文件链接" "$(status /link-in.txt)" "403"
+check "默认拒绝经过目录链接的路径" "$(status /link-dir/target.txt)" "403"
+check "默认拒绝指向外部的链接" "$(status /link-out.txt)" "403"
+check "目录列表仍显示符号链接" \
+    "$(curl -s "$BASE_URL/?format=json" | grep -o '"name":"link-in.txt"')" \
+    '"name":"link-in.txt"'
+check "默认标记符号链接但不给出目标" \
+    "$(curl -s "$BASE_URL/?format=json" | grep -o '"name":"link-in.txt"[^}]*}' | grep -o '"is_symlink":[^}]*')" \
+    '"is_symlink":true'
+
+stop_server
+start_server --follow-symlinks "$ROOT"
+
+check "跟随根目录内的文件链接" "$(curl -s "$BASE_URL/link-in.txt")" "inside"
+check "跟随根目录内的目录链接" "$(curl -s "$BASE_URL/link-dir/target.txt")" "inside"
+check "仍然拒绝指向外部的链接" "$(status /link-out.txt)" "403"
+LISTING=$(curl -s "$BASE_URL/?format=json")
+check "列表给出根目录内链接的目标" \
+    "$(echo "$LISTING" | grep -o '"name":"link-in.txt"[^}]*}' | grep -o '"target":"[^"]*"')" \
+    '"target":"inner/target.txt"'
+check "目录链接按目录显示" \
+    "$(echo "$LISTING" | grep -o '"name":"link-dir","is_dir":[a-z]*')" \
+    '"name":"link-dir","is_dir":true'
+check "不给出指向外部的链接目标" \
+    "$(echo "$LISTING" | grep -o '"name":"link-out.txt"[^}]*}' | grep -c '"target"')" \
+    "0"
+check "普通文件不标记为链接" \
+    "$(echo "$LISTING" | grep -o '"name":"inner","[^}]*}' | grep -o '"is_symlink":[a-z]*')" \
+    '"is_symlink":false'
+
+stop_server
+
+# ---------------------------------------------------------------
+# 小文件缓存命中时同样支持Range
+# ---------------------------------------------------------------
+printf '0123456789' > "$ROOT/digits.txt"
+
+start_server "$ROOT"
+
+curl -s -o /dev/null "$BASE_URL/digits.txt"
+check "缓存命中时返回206" "$(status /digits.txt -H 'Range: bytes=2-5')" "206"
+check "缓存命中时按区间切片" "$(curl -s -H 'Range: bytes=2-5' "$BASE_URL/digits.txt")" "2345"
+check "缓存命中时的Content-Range" \
+    "$(header /digits.txt content-range -H 'Range: bytes=7-')" "bytes 7-9/10"
+check "后缀区间取最后几个字节" "$(curl -s -H 'Range: bytes=-3' "$BASE_URL/digits.txt")" "789"
+check "后缀区间的Content-Range" "$(header /digits.txt content-range -H 'Range: bytes=-3')" "bytes 7-9/10"
+check "后缀比文件长时返回整个文件" "$(header /digits.txt content-range -H 'Range: bytes=-500')" "bytes 0-9/10"
+check "后缀为0返回416" "$(status /digits.txt -H 'Range: bytes=-0')" "416"
+check "起点超出文件返回416" "$(status /digits.txt -H 'Range: bytes=10-')" "416"
+check "416带文件大小" "$(header /digits.txt content-range -H 'Range: bytes=10-20')" "bytes */10"
+check "结尾超出文件时截到最后一个字节" "$(curl -s -H 'Range: bytes=8-100' "$BASE_URL/digits.txt")" "89"
+check "无法解析的Range返回完整文件" "$(status /digits.txt -H 'Range: bytes=abc')" "200"
+
+stop_server
+
+# ---------------------------------------------------------------
+# If-Range: 文件没变时继续返回区间，变了返回完整文件
+# ---------------------------------------------------------------
+start_server "$ROOT"
+
+ETAG="$(header /digits.txt etag)"
+LAST_MODIFIED="$(header /digits.txt last-modified)"
+check "ETag匹配时返回206" "$(status /digits.txt -H 'Range: bytes=2-5' -H "If-Range: $ETAG")" "206"
+check "日期匹配时返回206" \
+    "$(status /digits.txt -H 'Range: bytes=2-5' -H "If-Range: $LAST_MODIFIED")" "206"
+check "ETag不匹配时返回完整文件" \
+    "$(curl -s -H 'Range: bytes=2-5' -H 'If-Range: "stale"' "$BASE_URL/digits.txt")" "0123456789"
+check "日期不匹配时返回200" \
+    "$(status /digits.txt -H 'Range: bytes=2-5' -H 'If-Range: Thu, 01 Jan 1970 00:00:00 GMT')" "200"
+
+stop_server
+
+# ---------------------------------------------------------------
+# 普通文件、目录和特殊文件节点
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/plain-dir"
+echo 'plain' > "$ROOT/plain.txt"
+mkfifo "$ROOT/pipe"
+
+start_server --no-index "$ROOT"
+
+check "普通文件返回200" "$(status /plain.txt)" "200"
+check "目录返回200" "$(status /plain-dir/)" "200"
+check "fifo返回404" "$(status /pipe)" "404"
+
+stop_server
+
+# ---------------------------------------------------------------
+# Cache-Control
+# ---------------------------------------------------------------
+start_server --cache-control-max-age 300 "$ROOT"
+
+check "文件使用配置的max-age" "$(header /plain.txt cache-control)" "public, max-age=300"
+check "目录列表不缓存" "$(header /plain-dir/ cache-control)" "no-cache"
+
+stop_server
+start_server --auth user:pass "$ROOT"
+
+check "启用认证时只允许私有缓存" \
+    "$(header /plain.txt cache-control -u user:pass)" "private, max-age=60"
+
+stop_server
+
+# ---------------------------------------------------------------
+# IP允许/拒绝列表
+# ---------------------------------------------------------------
+start_server --allow-ip 127.0.0.0/8 "$ROOT"
+check "允许列表内的地址可以访问" "$(status /plain.txt)" "200"
+stop_server
+
+start_server --allow-ip 10.0.0.0/8 "$ROOT"
+check "不在允许列表内返回403" "$(status /plain.txt)" "403"
+stop_server
+
+start_server --allow-ip 127.0.0.0/8 --deny-ip 127.0.0.1 "$ROOT"
+check "拒绝规则优先于允许规则" "$(status /plain.txt)" "403"
+stop_server
+
+# ---------------------------------------------------------------
+# 反向代理后面的客户端地址
+# ---------------------------------------------------------------
+start_server --deny-ip 10.1.2.3 "$ROOT"
+check "未信任代理时忽略X-Forwarded-For" "$(status /plain.txt -H 'X-Forwarded-For: 10.1.2.3')" "200"
+stop_server
+
+start_server --deny-ip 10.1.2.3 --trust-proxy 127.0.0.1 --access-log "$WORK_DIR/proxy.log" "$ROOT"
+check "信任代理时使用X-Forwarded-For" "$(status /plain.txt -H 'X-Forwarded-For: 10.1.2.3')" "403"
+check "跳过受信任的代理" \
+    "$(status /plain.txt -H 'X-Forwarded-For: 10.1.2.3, 127.0.0.1')" "403"
+check "最右边的非代理地址才是客户端" \
+    "$(status /plain.txt -H 'X-Forwarded-For: 10.1.2.3, 192.0.2.8')" "200"
+check "没有X-Forwarded-For时使用X-Real-IP" "$(status /plain.txt -H 'X-Real-IP: 10.1.2.3')" "403"
+check "访问日志记录真实客户端" "$(tail -1 "$WORK_DIR/proxy.log" | cut -d' ' -f1)" "10.1.2.3"
+stop_server
+
+# ---------------------------------------------------------------
+# 自定义Content-Type
+# ---------------------------------------------------------------
+echo 'const a: number = 1;' > "$ROOT/main.TS"
+
+start_server --mime-override .ts=text/typescript "$ROOT"
+check "扩展名映射不区分大小写" "$(header /main.TS content-type)" "text/typescript"
+check "其他扩展名不受影响" "$(header /plain.txt content-type)" "text/plain"
+stop_server
+
+# ---------------------------------------------------------------
+# 健康检查不受认证影响
+# ---------------------------------------------------------------
+start_server --auth user:pass "$ROOT"
+check "启用认证时健康检查仍返回200" "$(status /_sfs/healthz)" "200"
+check "其他路径仍需认证" "$(status /plain.txt)" "401"
+stop_server
+
+# ---------------------------------------------------------------
+# 文件名中的控制字符不能导致panic
+# ---------------------------------------------------------------
+printf 'ctrl' > "$ROOT/$(printf 'bad\001name.txt')"
+
+start_server "$ROOT"
+check "控制字符文件名可以下载" "$(status '/bad%01name.txt?download=1')" "200"
+check "控制字符被替换" \
+    "$(header '/bad%01name.txt?download=1' content-disposition)" \
+    "attachment; filename=\"bad_name.txt\"; filename*=UTF-8''bad%01name.txt"
+check "服务仍然正常" "$(status /plain.txt)" "200"
+stop_server
+
+# ---------------------------------------------------------------
+# --watch: 修改时间不变的改写也能让缓存失效
+# ---------------------------------------------------------------
+echo 'AAAA' > "$ROOT/watched.txt"
+touch -r "$ROOT/watched.txt" "$WORK_DIR/mtime-ref"
+
+start_server --watch "$ROOT"
+curl -s -o /dev/null "$BASE_URL/watched.txt"
+echo 'BBBB' > "$ROOT/watched.txt"
+touch -r "$WORK_DIR/mtime-ref" "$ROOT/watched.txt"
+sleep 0.5
+check "文件改写后不再返回旧的缓存" "$(curl -s "$BASE_URL/watched.txt")" "BBBB"
+stop_server
+
+# ---------------------------------------------------------------
+# 自定义错误页面
+# ---------------------------------------------------------------
+echo '<h1>not here</h1>' > "$ROOT/404.html"
+
+start_server --error-pages "$ROOT"
+check "错误页面保留404状态码" "$(status /missing.txt)" "404"
+check "返回404.html的内容" "$(curl -s "$BASE_URL/missing.txt")" "<h1>not here</h1>"
+check "没有对应页面时返回空响应" "$(curl -s "$BASE_URL/link-out.txt")" ""
+stop_server
+
+start_server "$ROOT"
+check "未启用时不使用错误页面" "$(curl -s "$BASE_URL/missing.txt")" ""
+stop_server
+
+# ---------------------------------------------------------------
+# 网页multipart上传
+# ---------------------------------------------------------------
+echo 'form upload' > "$WORK_DIR/form.txt"
+
+start_server --read-only false "$ROOT"
+check "上传到子目录" \
+    "$(curl -s -F "file=@$WORK_DIR/form.txt" "$BASE_URL/plain-dir")" \
+    '[{"name":"form.txt","status":201}]'
+check "上传的文件内容一致" "$(cat "$ROOT/plain-dir/form.txt")" "form upload"
+check "非法文件名被拒绝" \
+    "$(curl -s -F "file=@$WORK_DIR/form.txt;filename=.." "$BASE_URL/plain-dir")" \
+    '[{"name":"..","status":400}]'
+stop_server
+
+start_server "$ROOT"
+check "只读模式下不允许上传" "$(status /plain-dir -F "file=@$WORK_DIR/form.txt")" "405"
+stop_server
+
+# ---------------------------------------------------------------
+# ?view=1 文本预览
+# ---------------------------------------------------------------
+printf 'fn main() {\n    let a = 1 < 2;\n}\n' > "$ROOT/main.rs"
+printf '\000\001\002' > "$ROOT/blob.bin"
+head -c 2000000 /dev/zero | tr '\0' 'a' > "$ROOT/huge.txt"
+
+start_server "$ROOT"
+check "预览返回HTML" "$(header '/main.rs?view=1' content-type)" "text/html; charset=utf-8"
+check "预览内容按行转义" \
+    "$(curl -s "$BASE_URL/main.rs?view=1" | grep -o '<span class="line">    let a = 1 &lt; 2;</span>')" \
+    '<span class="line">    let a = 1 &lt; 2;</span>'
+check "二进制文件不预览" "$(curl -s "$BASE_URL/blob.bin?view=1" | grep -c '无法预览')" "1"
+check "大文件不预览" "$(curl -s "$BASE_URL/huge.txt?view=1" | grep -c '无法预览')" "1"
+check "同时带download时仍然下载" "$(curl -s "$BASE_URL/main.rs?view=1&download=1" | head -1)" "fn main() {"
+check "预览页面的下载链接指向文件本身" \
+    "$(curl -s "$BASE_URL/main.rs?view=1" | grep -o 'href="[^"]*?download=1"')" 'href="/main%2Ers?download=1"'
+stop_server
+rm "$ROOT/main.rs" "$ROOT/blob.bin" "$ROOT/huge.txt"
+
+# ---------------------------------------------------------------
+# Markdown渲染
+# ---------------------------------------------------------------
+printf '# Title\n\n<script>alert(1)</script>\n\n[bad](javascript:alert(1)) [ok](other.md)\n' > "$ROOT/doc.md"
+
+start_server "$ROOT"
+check "Markdown渲染成HTML" "$(curl -s "$BASE_URL/doc.md" | grep -o '<h1>Title</h1>')" "<h1>Title</h1>"
+check "内嵌HTML被转义" "$(curl -s "$BASE_URL/doc.md" | grep -c '<script>alert')" "0"
+check "javascript链接被替换" "$(curl -s "$BASE_URL/doc.md" | grep -o '<a href="[^"]*">bad')" '<a href="#">bad'
+check "相对链接保留" "$(curl -s "$BASE_URL/doc.md" | grep -o '<a href="[^"]*">ok')" '<a href="other.md">ok'
+check "download时返回原始内容" "$(curl -s "$BASE_URL/doc.md?download=1" | head -1)" "# Title"
+check "Markdown页面的下载链接不需要重定向" \
+    "$(status "$(curl -s "$BASE_URL/doc.md" | grep -o 'href="[^"]*?download=1"' | cut -d'"' -f2)")" "200"
+stop_server
+rm "$ROOT/doc.md"
+
+# ---------------------------------------------------------------
+# 图片缩略图
+# ---------------------------------------------------------------
+# make_png 文件 宽 高，生成纯色PNG
+make_png() {
+    python3 - "$@" <<'PY'
+import struct, sys, zlib
+path, width, height = sys.argv[1], int(sys.argv[2]), int(sys.argv[3])
+def chunk(kind, data):
+    return struct.pack(">I", len(data)) + kind + data + struct.pack(">I", zlib.crc32(kind + data))
+rows = b"".join(b"\x00" + b"\x80\x40\x20" * width for _ in range(height))
+with open(path, "wb") as f:
+    f.write(b"\x89PNG\r\n\x1a\n")
+    f.write(chunk(b"IHDR", struct.pack(">IIBBBBB", width, height, 8, 2, 0, 0, 0)))
+    f.write(chunk(b"IDAT", zlib.compress(rows, 9)))
+    f.write(chunk(b"IEND", b""))
+PY
+}
+make_png "$ROOT/photo.png" 300 200
+make_png "$ROOT/bomb.png" 20000 1
+
+start_server "$ROOT"
+check "返回JPEG缩略图" "$(header '/photo.png?thumb=64' content-type)" "image/jpeg"
+check "缩略图是JPEG数据" \
+    "$(curl -s "$BASE_URL/photo.png?thumb=64" | head -c 2 | od -An -tx1 | tr -d ' ')" "ffd8"
+check "尺寸不合法返回400" "$(status '/photo.png?thumb=5000')" "400"
+check "非图片返回415" "$(status '/plain.txt?thumb=64')" "415"
+check "超大尺寸的图片拒绝解码" "$(status '/bomb.png?thumb=64')" "415"
+stop_server
+rm "$ROOT/photo.png" "$ROOT/bomb.png"
+
+# ---------------------------------------------------------------
+# 预压缩的 .br/.gz 文件
+# ---------------------------------------------------------------
+echo 'console.log(1);' > "$ROOT/app.js"
+gzip -k "$ROOT/app.js"
+printf 'fake-br' > "$ROOT/app.js.br"
+
+start_server "$ROOT"
+JS_TYPE="$(header /app.js content-type)"
+check "客户端接受br时发送.br文件" "$(curl -s -H 'Accept-Encoding: gzip, br' "$BASE_URL/app.js")" "fake-br"
+check "br响应的Content-Encoding" "$(header /app.js content-encoding -H 'Accept-Encoding: br')" "br"
+check "Content-Type仍按原文件" "$(header /app.js content-type -H 'Accept-Encoding: br')" "$JS_TYPE"
+check "只接受gzip时发送.gz文件" \
+    "$(curl -s -H 'Accept-Encoding: gzip' "$BASE_URL/app.js" | gunzip)" "console.log(1);"
+check "q=0视为不接受" \
+    "$(header /app.js content-encoding -H 'Accept-Encoding: br;q=0, gzip')" "gzip"
+check "不接受压缩时发送原文件" "$(curl -s "$BASE_URL/app.js")" "console.log(1);"
+check "响应带Vary" "$(header /app.js vary | grep -c accept-encoding)" "1"
+stop_server
+rm "$ROOT/app.js" "$ROOT/app.js.gz" "$ROOT/app.js.br"
+
+# ---------------------------------------------------------------
+# 复制链接使用请求的Host
+# ---------------------------------------------------------------
+start_server "$ROOT"
+check "页面中的绝对地址取自Host头" \
+    "$(curl -s -H 'Host: files.example.com' "$BASE_URL/plain-dir/" | grep -o 'const baseUrl = [^;]*')" \
+    'const baseUrl = "http://files.example.com" || window.location.origin'
+check "Host头中的<被转义" \
+    "$(curl -s -H 'Host: a</script>' "$BASE_URL/plain-dir/" | grep -c 'a</script>')" "0"
+stop_server
+
+# ---------------------------------------------------------------
+# 不支持的方法返回405和Allow
+# ---------------------------------------------------------------
+start_server "$ROOT"
+check "只读模式下DELETE返回405" "$(status /plain.txt -X DELETE)" "405"
+check "只读模式下的Allow" "$(header /plain.txt allow -X DELETE)" "GET,HEAD"
+check "只读模式下POST根目录的Allow" "$(header / allow -X POST)" "GET,HEAD"
+stop_server
+
+start_server --read-only false "$ROOT"
+check "可写时文件路径的Allow" "$(header /plain.txt allow -X PATCH)" "GET,HEAD,PUT,POST,DELETE"
+check "可写时根目录的Allow" "$(header / allow -X DELETE)" "GET,HEAD,POST"
+check "内置接口的Allow" "$(header /_sfs/healthz allow -X POST)" "GET,HEAD"
+stop_server
+
+# ---------------------------------------------------------------
+# 路径深度和上传大小限制
+# ---------------------------------------------------------------
+head -c 4096 /dev/zero > "$WORK_DIR/4k.bin"
+
+start_server --read-only false --max-upload-size 1K --max-path-depth 3 "$ROOT"
+check "路径层级过深返回400" "$(status /a/b/c/d)" "400"
+check "层级未超限时正常处理" "$(status /a/b/c)" "404"
+check "PUT超过大小限制返回413" \
+    "$(status /too-big.bin -T "$WORK_DIR/4k.bin")" "413"
+check "超限的上传不留下文件" "$(ls -A "$ROOT" | grep -c too-big)" "0"
+check "multipart超过大小限制返回413" \
+    "$(curl -s -F "file=@$WORK_DIR/4k.bin" "$BASE_URL/plain-dir" -w ' %{http_code}' | grep -o '413' | head -1)" "413"
+check "未超限的上传正常保存" "$(status /small.txt -T "$ROOT/plain.txt")" "201"
+stop_server
+rm -f "$ROOT/small.txt"
+
+# ---------------------------------------------------------------
+# 挂在反向代理的子路径下
+# ---------------------------------------------------------------
+start_server --base-path /files/ "$ROOT"
+check "前缀本身就是根目录" "$(status /files)" "200"
+check "带斜杠的前缀也是根目录" "$(status /files/)" "200"
+check "前缀以外的路径返回404" "$(status /plain.txt)" "404"
+check "去掉前缀后下载文件" "$(curl -s "$BASE_URL/files/plain.txt")" "$(cat "$ROOT/plain.txt")"
+check "列表中的链接带前缀" \
+    "$(curl -s "$BASE_URL/files/?format=json" | grep -c '"url":"/files/plain%2Etxt"')" "1"
+check "子目录的上级链接指回前缀" \
+    "$(curl -s "$BASE_URL/files/plain-dir?format=json" | grep -c '"url":"/files/"')" "1"
+check "内置接口也在前缀下" "$(status /files/_sfs/healthz)" "200"
+stop_server
+
+# ---------------------------------------------------------------
+# JSON格式日志
+# ---------------------------------------------------------------
+start_server --log-format json "$ROOT"
+curl -s -o /dev/null "$BASE_URL/plain.txt"
+sleep 0.2
+check "每一行输出都是JSON" "$(grep -vc '^{' "$WORK_DIR/server.log")" "0"
+check "请求日志带结构化字段" \
+    "$(grep -c '"method":"GET","path":"/plain.txt","status":200,"bytes":6' "$WORK_DIR/server.log")" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 目录打包成ZIP下载
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/zip-dir/sub/empty"
+seq 1 20000 > "$ROOT/zip-dir/nums.txt"
+echo 'nested' > "$ROOT/zip-dir/sub/a.txt"
+echo 'hidden' > "$ROOT/zip-dir/.hidden"
+
+# zip_info 压缩包，输出每一项的名称和压缩方式
+zip_info() {
+    python3 -c "import sys, zipfile
+z = zipfile.ZipFile(sys.argv[1])
+assert z.testzip() is None
+print(' '.join(f'{i.filename}:{i.compress_type}' for i in z.infolist()))" "$1"
+}
+
+start_server "$ROOT"
+check "ZIP的Content-Type" "$(header '/zip-dir?download=zip' content-type)" "application/zip"
+curl -s -o "$WORK_DIR/store.zip" "$BASE_URL/zip-dir?download=zip"
+check "默认只打包不压缩，保留空目录，跳过隐藏文件" "$(zip_info "$WORK_DIR/store.zip")" \
+    "nums.txt:0 sub/:0 sub/a.txt:0 sub/empty/:0"
+curl -s -o "$WORK_DIR/fast.zip" "$BASE_URL/zip-dir?download=zip&level=fast"
+curl -s -o "$WORK_DIR/best.zip" "$BASE_URL/zip-dir?download=zip&level=best"
+check "level=best使用deflate" "$(zip_info "$WORK_DIR/best.zip")" \
+    "nums.txt:8 sub/:0 sub/a.txt:8 sub/empty/:0"
+check "best比fast压缩得更小" \
+    "$([ "$(stat -c %s "$WORK_DIR/best.zip")" -lt "$(stat -c %s "$WORK_DIR/fast.zip")" ] && echo yes)" "yes"
+check "未知的level返回400" "$(status '/zip-dir?download=zip&level=max')" "400"
+chmod 755 "$ROOT/zip-dir/nums.txt"
+check "tar.gz的Content-Type" "$(header '/zip-dir?download=tar.gz' content-type)" "application/gzip"
+check "tar.gz的文件名" \
+    "$(header '/zip-dir?download=tar.gz' content-disposition | grep -o 'filename="[^"]*"')" 'filename="zip-dir.tar.gz"'
+curl -s -o "$WORK_DIR/dir.tar.gz" "$BASE_URL/zip-dir?download=tar.gz"
+check "tar.gz的路径与ZIP一致" "$(tar tzf "$WORK_DIR/dir.tar.gz" | tr '\n' ' ')" \
+    "nums.txt sub/ sub/a.txt sub/empty/ "
+check "tar.gz保留权限位" "$(tar tzvf "$WORK_DIR/dir.tar.gz" nums.txt | cut -c1-10)" "-rwxr-xr-x"
+check "tar.gz内容完整" "$(tar xzOf "$WORK_DIR/dir.tar.gz" sub/a.txt)" "nested"
+check "压缩包声明不支持Range" "$(header '/zip-dir?download=zip' accept-ranges)" "none"
+check "压缩包忽略Range返回完整内容" \
+    "$(curl -s -o /dev/null -w '%{http_code}' -H 'Range: bytes=100-' "$BASE_URL/zip-dir?download=tar.gz")" "200"
+check "忽略Range时不带Content-Range" "$(header '/zip-dir?download=zip' content-range -H 'Range: bytes=100-')" ""
+stop_server
+rm -rf "$ROOT/zip-dir"
+
+# ---------------------------------------------------------------
+# 没有图形界面时 --open 只给出警告
+# ---------------------------------------------------------------
+DISPLAY= WAYLAND_DISPLAY= start_server --open --base-path /files "$ROOT"
+check "无图形界面时服务照常启动" "$(status /files/plain.txt)" "200"
+check "警告中的地址带上前缀" \
+    "$(grep -c "not opening a browser for http://127.0.0.1:$SERVER_PORT/files/" "$WORK_DIR/server.log")" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 文件的SHA-256校验和
+# ---------------------------------------------------------------
+start_server "$ROOT"
+check "返回SHA-256摘要" "$(curl -s "$BASE_URL/plain.txt?checksum=sha256")" \
+    "$(sha256sum "$ROOT/plain.txt" | cut -d' ' -f1)"
+check "校验和是纯文本" "$(header '/plain.txt?checksum=sha256' content-type)" "text/plain; charset=utf-8"
+check "不支持的算法返回400" "$(status '/plain.txt?checksum=md5')" "400"
+echo 'changed' > "$ROOT/checksum.txt"
+curl -s -o /dev/null "$BASE_URL/checksum.txt?checksum=sha256"
+sleep 1
+echo 'changed again' > "$ROOT/checksum.txt"
+check "文件修改后重新计算" "$(curl -s "$BASE_URL/checksum.txt?checksum=sha256")" \
+    "$(sha256sum "$ROOT/checksum.txt" | cut -d' ' -f1)"
+stop_server
+rm "$ROOT/checksum.txt"
+
+# ---------------------------------------------------------------
+# 目录是否排在文件前面
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/sort-dir/b-dir"
+touch "$ROOT/sort-dir/a.txt" "$ROOT/sort-dir/c.txt"
+
+# names 路径，按顺序输出JSON列表中的名称
+names() {
+    curl -s "$BASE_URL$1" | grep -o '"name":"[^"]*"' | cut -d'"' -f4 | tr '\n' ' '
+}
+
+start_server "$ROOT"
+check "默认目录在前" "$(names '/sort-dir?format=json')" ".. b-dir a.txt c.txt "
+stop_server
+
+start_server --sort-dirs-first false "$ROOT"
+check "关闭后按名称混合排序，..仍在最前" "$(names '/sort-dir?format=json')" ".. a.txt b-dir c.txt "
+check "倒序时..仍在最前" "$(names '/sort-dir?format=json&order=desc')" ".. c.txt b-dir a.txt "
+stop_server
+rm -rf "$ROOT/sort-dir"
+
+# ---------------------------------------------------------------
+# 流式传输的大文件同样支持条件请求
+# ---------------------------------------------------------------
+head -c 5000000 /dev/zero > "$ROOT/large.bin"
+
+start_server "$ROOT"
+LARGE_MODIFIED="$(header /large.bin last-modified)"
+LARGE_ETAG="$(header /large.bin etag)"
+check "大文件带Last-Modified" "$([ -n "$LARGE_MODIFIED" ] && echo yes)" "yes"
+check "大文件If-Modified-Since未变返回304" \
+    "$(status /large.bin -H "If-Modified-Since: $LARGE_MODIFIED")" "304"
+check "大文件If-None-Match未变返回304" "$(status /large.bin -H "If-None-Match: $LARGE_ETAG")" "304"
+check "304不发送文件内容" \
+    "$(curl -s -H "If-None-Match: $LARGE_ETAG" "$BASE_URL/large.bin" | wc -c)" "0"
+touch -d '+1 hour' "$ROOT/large.bin"
+check "文件修改后返回200" "$(status /large.bin -H "If-None-Match: $LARGE_ETAG")" "200"
+stop_server
+rm "$ROOT/large.bin"
+
+# ---------------------------------------------------------------
+# 按客户端IP共享限速额度
+# ---------------------------------------------------------------
+head -c 2000000 /dev/zero > "$ROOT/shared.bin"
+
+# elapsed_ms 命令...，输出执行耗时（毫秒）
+elapsed_ms() {
+    local start end
+    start=$(date +%s%N)
+    "$@"
+    end=$(date +%s%N)
+    echo $(( (end - start) / 1000000 ))
+}
+
+# parallel_download XFF1 XFF2，以两个客户端地址同时下载
+parallel_download() {
+    curl -s -o /dev/null -H "X-Forwarded-For: $1" "$BASE_URL/shared.bin" &
+    curl -s -o /dev/null -H "X-Forwarded-For: $2" "$BASE_URL/shared.bin"
+    wait
+}
+
+start_server --rate-limit 1M --rate-limit-per-ip --trust-proxy 127.0.0.1 "$ROOT"
+check "同一客户端的并发下载共用额度" \
+    "$([ "$(elapsed_ms parallel_download 10.0.0.1 10.0.0.1)" -ge 2500 ] && echo yes)" "yes"
+check "不同客户端各自计算额度" \
+    "$([ "$(elapsed_ms parallel_download 10.0.0.2 10.0.0.3)" -lt 2500 ] && echo yes)" "yes"
+check "限速不影响Content-Length" "$(header /shared.bin content-length)" "2000000"
+stop_server
+rm "$ROOT/shared.bin"
+
+# ---------------------------------------------------------------
+# 全局限速与吞吐量
+# ---------------------------------------------------------------
+head -c 2000000 /dev/zero > "$ROOT/shared.bin"
+
+start_server --global-rate 1M --trust-proxy 127.0.0.1 "$ROOT"
+check "不同客户端共用全局额度" \
+    "$([ "$(elapsed_ms parallel_download 10.0.0.2 10.0.0.3)" -ge 2500 ] && echo yes)" "yes"
+check "metrics包含吞吐量" \
+    "$(curl -s "$BASE_URL/_sfs/metrics" | grep -c '^http_file_server_throughput_bytes_per_second ')" "1"
+check "metrics包含全局限速" \
+    "$(curl -s "$BASE_URL/_sfs/metrics" | grep '^http_file_server_global_rate_bytes_per_second ')" \
+    "http_file_server_global_rate_bytes_per_second 1048576"
+stop_server
+rm "$ROOT/shared.bin"
+
+# ---------------------------------------------------------------
+# 空目录显示为空状态
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/empty-dir"
+
+start_server "$ROOT"
+check "只有..的子目录标记为空" \
+    "$(curl -s "$BASE_URL/empty-dir/" | grep -o 'const isEmpty = [a-z]*')" "const isEmpty = true"
+check "有内容的目录不标记为空" \
+    "$(curl -s "$BASE_URL/" | grep -o 'const isEmpty = [a-z]*')" "const isEmpty = false"
+stop_server
+rmdir "$ROOT/empty-dir"
+
+# ---------------------------------------------------------------
+# 单文件模式
+# ---------------------------------------------------------------
+start_server "$ROOT/plain.txt"
+check "根路径返回该文件" "$(curl -s "$BASE_URL/")" "$(cat "$ROOT/plain.txt")"
+check "下载文件名取自文件" \
+    "$(header '/?download=1' content-disposition | grep -o 'filename="[^"]*"')" 'filename="plain.txt"'
+check "同目录的其他文件返回404" "$(status /plain.txt)" "404"
+check "单文件模式不提供搜索" "$(status '/_sfs/search?q=plain')" "404"
+check "单文件模式只读" "$(curl -s -o /dev/null -w '%{http_code}' -X PUT --data x "$BASE_URL/new.txt")" "405"
+check "单文件模式下根路径的二维码" "$(status '/?qr=1')" "200"
+check "单文件模式下其他路径不生成二维码" "$(status '/plain.txt?qr=1')" "404"
+stop_server
+
+# ---------------------------------------------------------------
+# 目录条目上限
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/many" "$ROOT/few"
+for i in $(seq 1 30); do touch "$ROOT/many/f$i"; done
+touch "$ROOT/few/a" "$ROOT/few/b"
+
+start_server --max-listing-entries 10 "$ROOT"
+check "超出上限时只返回前N项" \
+    "$(curl -s "$BASE_URL/many?format=json" | python3 -c 'import sys, json; print(sum(e["name"] != ".." for e in json.load(sys.stdin)))')" "10"
+check "JSON响应标记被截断" "$(header '/many?format=json' x-listing-truncated)" "true"
+check "网页显示截断提示" "$(curl -s "$BASE_URL/many/" | grep -c '只显示前 10 项')" "1"
+check "未超出上限时不标记" "$(header '/few?format=json' x-listing-truncated)" "false"
+stop_server
+rm -rf "$ROOT/many" "$ROOT/few"
+
+# ---------------------------------------------------------------
+# 文件信息
+# ---------------------------------------------------------------
+start_server "$ROOT"
+check "stat返回文件信息" \
+    "$(curl -s "$BASE_URL/plain.txt?stat=1" | python3 -c 'import sys, json; d = json.load(sys.stdin); print(d["name"], d["size"], d["mime"], "sha256" in d)')" \
+    "plain.txt $(stat -c %s "$ROOT/plain.txt") text/plain False"
+check "stat的修改时间" \
+    "$(curl -s "$BASE_URL/plain.txt?stat=1" | python3 -c 'import sys, json; print(json.load(sys.stdin)["modified"])')" \
+    "$(stat -c %Y "$ROOT/plain.txt")"
+check "显式要求时附带sha256" \
+    "$(curl -s "$BASE_URL/plain.txt?stat=1&checksum=sha256" | python3 -c 'import sys, json; print(json.load(sys.stdin)["sha256"])')" \
+    "$(sha256sum "$ROOT/plain.txt" | cut -d' ' -f1)"
+check "stat的Content-Type" "$(header '/plain.txt?stat=1' content-type)" "application/json"
+stop_server
+
+# ---------------------------------------------------------------
+# 文件在解析路径之后被删除
+# ---------------------------------------------------------------
+start_server "$ROOT"
+# 后台不停地创建和删除文件，请求会落在各个时间点上，只能是200或404，不能是500
+( while true; do echo racing > "$ROOT/racy.txt"; rm -f "$ROOT/racy.txt"; done ) &
+RACER_PID=$!
+RACE_STATUSES=$(for _ in $(seq 1 300); do status /racy.txt; echo; done | sort -u | tr '\n' ' ')
+kill $RACER_PID
+wait $RACER_PID 2>/dev/null
+rm -f "$ROOT/racy.txt"
+check "文件消失时返回404而不是500" \
+    "$(echo "$RACE_STATUSES" | tr ' ' '\n' | grep -v '^$' | grep -vx '200\|404' | head -1)" ""
+stop_server
+
+# ---------------------------------------------------------------
+# 可续传的上传（tus）
+# ---------------------------------------------------------------
+head -c 300000 /dev/urandom > "$WORK_DIR/tus.bin"
+
+# tus_patch 地址 偏移量 [curl参数...]，从标准输入读取数据
+tus_patch() {
+    curl -s -o /dev/null -w "%{http_code}" -X PATCH -H "Tus-Resumable: 1.0.0" \
+        -H "Upload-Offset: $2" -H "Content-Type: application/offset+octet-stream" \
+        --data-binary @- "${@:3}" "$BASE_URL$1"
+}
+
+# tus_offset 地址，输出服务器已收到的字节数
+tus_offset() {
+    header "$1" upload-offset -I -H "Tus-Resumable: 1.0.0"
+}
+
+start_server --read-only false "$ROOT"
+TUS_LOCATION=$(header /_sfs/tus location -X POST -H "Tus-Resumable: 1.0.0" -H "Upload-Length: 300000" \
+    -H "Upload-Metadata: path $(printf 'tus-dir/tus.bin' | base64)")
+check "创建上传返回地址" "$(echo "$TUS_LOCATION" | grep -c '^/_sfs/tus/[0-9a-f]\{32\}$')" "1"
+check "新建的上传偏移量为0" "$(tus_offset "$TUS_LOCATION")" "0"
+# 限速发送并中途断开，已收到的部分应当保留
+head -c 200000 "$WORK_DIR/tus.bin" | tus_patch "$TUS_LOCATION" 0 --limit-rate 100K --max-time 1 > /dev/null
+TUS_OFFSET=$(tus_offset "$TUS_LOCATION")
+check "断开后保留已收到的数据" "$([ "$TUS_OFFSET" -gt 0 ] && [ "$TUS_OFFSET" -lt 200000 ] && echo yes)" "yes"
+check "偏移量不符返回409" "$(echo x | tus_patch "$TUS_LOCATION" 1)" "409"
+check "从断点继续上传" \
+    "$(tail -c +$((TUS_OFFSET + 1)) "$WORK_DIR/tus.bin" | tus_patch "$TUS_LOCATION" "$TUS_OFFSET")" "204"
+check "完成后文件内容一致" "$(cmp -s "$WORK_DIR/tus.bin" "$ROOT/tus-dir/tus.bin" && echo same)" "same"
+check "完成后不留临时文件" "$(ls -A "$ROOT/tus-dir")" "tus.bin"
+check "完成后上传地址失效" "$(status "$TUS_LOCATION" -I -H 'Tus-Resumable: 1.0.0')" "404"
+check "缺少Tus-Resumable返回412" "$(status /_sfs/tus -X POST -H 'Upload-Length: 1')" "412"
+stop_server
+rm -rf "$ROOT/tus-dir"
+
+# 根目录中名为tus的目录不受上传接口影响
+mkdir "$ROOT/tus"
+echo hello > "$ROOT/tus/a.txt"
+start_server --read-only false "$ROOT"
+check "可写时tus目录中的文件照常下载" "$(curl -s "$BASE_URL/tus/a.txt")" "hello"
+check "可写时tus目录照常列出" "$(status /tus/)" "200"
+stop_server
+rm -r "$ROOT/tus"
+
+start_server "$ROOT"
+check "只读模式不接受tus上传" \
+    "$(status /_sfs/tus -X POST -H 'Tus-Resumable: 1.0.0' -H 'Upload-Length: 1')" "405"
+stop_server
+
+# ---------------------------------------------------------------
+# DELETE：删除文件和空目录，--soft-delete 移到 .trash
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/del/empty" "$ROOT/del/full"
+echo 'gone' > "$ROOT/del/a.txt"
+echo 'keep' > "$ROOT/del/full/b.txt"
+echo 'secret' > "$WORK_DIR/outside/del.txt"
+ln -s "$WORK_DIR/outside/del.txt" "$ROOT/del/link-out.txt"
+
+start_server "$ROOT"
+check "只读模式下不能删除" "$(status /del/a.txt -X DELETE)" "405"
+stop_server
+
+start_server --read-only false "$ROOT"
+check "删除文件返回204" "$(status /del/a.txt -X DELETE)" "204"
+check "文件已被删除" "$([ -e "$ROOT/del/a.txt" ] && echo yes || echo no)" "no"
+check "删除不存在的文件返回404" "$(status /del/a.txt -X DELETE)" "404"
+check "删除空目录" "$(status /del/empty -X DELETE)" "204"
+check "非空目录返回409" "$(status /del/full -X DELETE)" "409"
+check "非空目录仍在" "$(cat "$ROOT/del/full/b.txt")" "keep"
+check "不能删除挂载目录本身" "$(status /. -X DELETE --path-as-is)" "403"
+check "目录穿越被拒绝" "$(status /del/..%2F..%2Fplain.txt -X DELETE)" "403"
+check "默认拒绝删除符号链接" "$(status /del/link-out.txt -X DELETE)" "403"
+check "链接指向的文件仍在" "$(cat "$WORK_DIR/outside/del.txt")" "secret"
+stop_server
+
+start_server --read-only false --follow-symlinks "$ROOT"
+check "跟随链接时只删除链接本身" "$(status /del/link-out.txt -X DELETE)" "204"
+check "链接已删除" "$([ -L "$ROOT/del/link-out.txt" ] && echo yes || echo no)" "no"
+check "链接指向的文件仍在" "$(cat "$WORK_DIR/outside/del.txt")" "secret"
+stop_server
+
+echo 'first' > "$ROOT/del/full/c.txt"
+start_server --read-only false --soft-delete "$ROOT"
+check "软删除返回204" "$(status /del/full/c.txt -X DELETE)" "204"
+check "软删除移到回收站" "$(cat "$ROOT/.trash/del/full/c.txt")" "first"
+echo 'second' > "$ROOT/del/full/c.txt"
+check "回收站同名时不覆盖" "$(status /del/full/c.txt -X DELETE)" "204"
+check "回收站保留两份" "$(ls "$ROOT/.trash/del/full" | wc -l | tr -d ' ')" "2"
+check "回收站中的文件再删除一次真正删掉" "$(status /.trash/del/full/c.txt -X DELETE)" "204"
+check "回收站中的文件已删除" "$([ -e "$ROOT/.trash/del/full/c.txt" ] && echo yes || echo no)" "no"
+stop_server
+rm -rf "$ROOT/del" "$ROOT/.trash"
+
+# ---------------------------------------------------------------
+# 视频拖动进度：inline + Range，覆盖缓存的小文件和流式的大文件
+# ---------------------------------------------------------------
+head -c 5000000 /dev/urandom > "$ROOT/movie.mp4"
+head -c 100000 /dev/urandom > "$ROOT/clip.mp4"
+
+start_server "$ROOT"
+for video in movie.mp4 clip.mp4; do
+    size=$(wc -c < "$ROOT/$video" | tr -d ' ')
+    check "$video 以inline返回" "$(header /$video content-disposition | cut -d';' -f1)" "inline"
+    check "$video 声明支持Range" "$(header /$video accept-ranges)" "bytes"
+    check "$video 首次请求bytes=0-返回206" "$(status /$video -H 'Range: bytes=0-')" "206"
+    check "$video bytes=0-的Content-Range" \
+        "$(header /$video content-range -H 'Range: bytes=0-')" "bytes 0-$((size - 1))/$size"
+    check "$video 拖动时的Content-Range" \
+        "$(header /$video content-range -H 'Range: bytes=50000-59999')" "bytes 50000-59999/$size"
+    check "$video 后缀区间读取末尾" \
+        "$(header /$video content-range -H 'Range: bytes=-500')" "bytes $((size - 500))-$((size - 1))/$size"
+    check "$video 后缀区间内容正确" \
+        "$(curl -s -H 'Range: bytes=-500' "$BASE_URL/$video" | sha256sum | cut -d' ' -f1)" \
+        "$(tail -c 500 "$ROOT/$video" | sha256sum | cut -d' ' -f1)"
+    check "$video 开放区间读到结尾" \
+        "$(curl -s -H 'Range: bytes=1000-' "$BASE_URL/$video" | wc -c)" "$((size - 1000))"
+    check "$video 超出文件的区间返回416" "$(header /$video content-range -H "Range: bytes=$size-")" "bytes */$size"
+    check "$video 拖动时返回对应的字节" \
+        "$(curl -s -H 'Range: bytes=50000-59999' "$BASE_URL/$video" | sha256sum | cut -d' ' -f1)" \
+        "$(tail -c +50001 "$ROOT/$video" | head -c 10000 | sha256sum | cut -d' ' -f1)"
+    check "$video 区间响应不压缩" \
+        "$(header /$video content-encoding -H 'Range: bytes=50000-59999' -H 'Accept-Encoding: gzip')" ""
+done
+stop_server
+rm -f "$ROOT/movie.mp4" "$ROOT/clip.mp4"
+
+# ---------------------------------------------------------------
+# 启动时检查服务目录可读，失败时直接退出
+# ---------------------------------------------------------------
+timeout 5 "$BINARY_PATH" -p $SERVER_PORT --mount "/x=$ROOT/plain.txt" "$ROOT" > "$WORK_DIR/startup.log" 2>&1
+check "挂载的不是目录时退出码非零" "$?" "1"
+check "提示无法列出目录" "$(grep -c "Cannot list $ROOT/plain.txt" "$WORK_DIR/startup.log")" "1"
+# root用户不受权限位限制，只在普通用户下检查
+if [ "$(id -u)" != "0" ]; then
+    mkdir -p "$WORK_DIR/locked"
+    chmod 000 "$WORK_DIR/locked"
+    timeout 5 "$BINARY_PATH" -p $SERVER_PORT "$WORK_DIR/locked" > "$WORK_DIR/startup.log" 2>&1
+    check "目录不可读时退出码非零" "$?" "1"
+    chmod 700 "$WORK_DIR/locked"
+fi
+
+# ---------------------------------------------------------------
+# 缓存参数：--cache-max-file-size 和 --no-cache
+# ---------------------------------------------------------------
+head -c 2000 /dev/zero > "$ROOT/cache-small.bin"
+head -c 20000 /dev/zero > "$ROOT/cache-large.bin"
+cache_hits() {
+    curl -s "$BASE_URL/_sfs/metrics" | grep '^http_file_server_cache_hits_total' | cut -d' ' -f2
+}
+
+start_server --cache-max-file-size 10K "$ROOT"
+for _ in 1 2; do
+    curl -s -o /dev/null "$BASE_URL/cache-small.bin"
+    curl -s -o /dev/null "$BASE_URL/cache-large.bin"
+done
+check "只有不超过上限的文件命中缓存" "$(cache_hits)" "1"
+stop_server
+
+start_server --no-cache "$ROOT"
+for _ in 1 2; do
+    curl -s -o /dev/null "$BASE_URL/cache-small.bin"
+done
+check "--no-cache 时不命中缓存" "$(cache_hits)" "0"
+check "--no-cache 时内容完整" "$(curl -s "$BASE_URL/cache-small.bin" | wc -c | tr -d ' ')" "2000"
+stop_server
+cache_misses() {
+    curl -s "$BASE_URL/_sfs/metrics" | grep '^http_file_server_cache_misses_total' | cut -d' ' -f2
+}
+
+start_server --cache-after 3 "$ROOT"
+for _ in 1 2; do
+    curl -s -o /dev/null "$BASE_URL/cache-small.bin"
+done
+check "--cache-after 之前不缓存" "$(cache_misses) $(cache_hits)" "0 0"
+check "未缓存时内容完整" "$(curl -s "$BASE_URL/cache-small.bin" | wc -c | tr -d ' ')" "2000"
+check "第N次请求时读进缓存" "$(cache_misses) $(cache_hits)" "1 0"
+curl -s -o /dev/null "$BASE_URL/cache-small.bin"
+check "之后命中缓存" "$(cache_hits)" "1"
+stop_server
+
+start_server "$ROOT"
+for _ in 1 2; do
+    curl -s -o /dev/null -H 'Cache-Control: no-store' "$BASE_URL/cache-small.bin"
+done
+check "no-store请求不放进缓存" "$(cache_misses) $(cache_hits)" "0 0"
+curl -s -o /dev/null "$BASE_URL/cache-small.bin"
+curl -s -o /dev/null -H 'Cache-Control: no-cache, no-store' "$BASE_URL/cache-small.bin"
+check "已缓存的文件no-store时仍可命中" "$(cache_misses) $(cache_hits)" "1 1"
+stop_server
+rm -f "$ROOT/cache-small.bin" "$ROOT/cache-large.bin"
+
+# ---------------------------------------------------------------
+# 慢客户端的背压：客户端读得慢时服务端不会提前把整个文件读进内存
+# ---------------------------------------------------------------
+truncate -s 200M "$ROOT/slow.bin"
+
+start_server "$ROOT"
+curl -s --limit-rate 500K -o /dev/null "$BASE_URL/slow.bin" &
+SLOW_PID=$!
+sleep 4
+# 没有背压时稀疏文件一瞬间就能读完，这里只允许socket缓冲区大小的提前量
+SERVED=$(curl -s "$BASE_URL/_sfs/metrics" | grep '^http_file_server_bytes_served_total' | cut -d' ' -f2)
+check "慢客户端时只读取socket能容纳的数据" "$([ "$SERVED" -lt 67108864 ] && echo yes)" "yes"
+kill $SLOW_PID 2>/dev/null
+wait $SLOW_PID 2>/dev/null
+stop_server
+rm -f "$ROOT/slow.bin"
+
+# ---------------------------------------------------------------
+# .serveignore：列表、搜索和打包中隐藏，--strict-ignore 时直接访问也返回404
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/ign/build" "$ROOT/ign/sub"
+printf '*.log\nbuild/\n' > "$ROOT/ign/.serveignore"
+printf '!keep.log\n' > "$ROOT/ign/sub/.serveignore"
+echo 'log' > "$ROOT/ign/debug.log"
+echo 'out' > "$ROOT/ign/build/out.bin"
+echo 'ok' > "$ROOT/ign/visible.txt"
+echo 'keep' > "$ROOT/ign/sub/keep.log"
+echo 'drop' > "$ROOT/ign/sub/drop.log"
+ign_names() {
+    curl -s "$BASE_URL/ign/$1?format=json" | grep -o '"name":"[^"]*"' | grep -v '"\.\."' | tr '\n' ' '
+}
+
+start_server "$ROOT"
+check "列表中隐藏匹配的文件和目录" "$(ign_names)" '"name":"sub" "name":"visible.txt" '
+check "下层规则可以重新放出文件" "$(ign_names sub)" '"name":"keep.log" '
+check "默认仍可直接访问" "$(curl -s "$BASE_URL/ign/debug.log")" "log"
+check "默认仍可访问被忽略目录中的文件" "$(status /ign/build/out.bin)" "200"
+check "搜索结果中不出现" "$(curl -s "$BASE_URL/_sfs/search?q=.log" | grep -o '"name":"[^"]*"' | tr '\n' ' ')" '"name":"ign/sub/keep.log" '
+check "打包时跳过" \
+    "$(curl -s "$BASE_URL/ign?download=tar.gz" | tar -tzf - | sort | tr '\n' ' ')" \
+    "sub/ sub/keep.log visible.txt "
+stop_server
+
+start_server --strict-ignore "$ROOT"
+check "严格模式下直接访问返回404" "$(status /ign/debug.log)" "404"
+check "严格模式下被忽略目录中的文件返回404" "$(status /ign/build/out.bin)" "404"
+check "严格模式下被忽略的目录返回404" "$(status /ign/build)" "404"
+check "严格模式下放出的文件仍可访问" "$(curl -s "$BASE_URL/ign/sub/keep.log")" "keep"
+check "严格模式下其他文件不受影响" "$(curl -s "$BASE_URL/ign/visible.txt")" "ok"
+check "二维码" "$(header '/ign/visible.txt?qr=1' content-type)" "image/svg+xml"
+check "不存在的路径不生成二维码" "$(status '/ign/missing.txt?qr=1')" "404"
+check "被忽略的文件不生成二维码" "$(status '/ign/debug.log?qr=1')" "404"
+stop_server
+rm -rf "$ROOT/ign"
+
+# ---------------------------------------------------------------
+# 路径规整：合并重复的分隔符、去掉 .，.. 在访问文件系统之前拒绝
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/norm/sub"
+echo 'norm' > "$ROOT/norm/sub/file.txt"
+
+start_server "$ROOT"
+check "重复的分隔符被合并" "$(curl -s --path-as-is "$BASE_URL/norm//sub///file.txt")" "norm"
+check "编码的分隔符被合并" "$(curl -s "$BASE_URL/norm%2F%2Fsub/file.txt")" "norm"
+check "点号分量被去掉" "$(curl -s --path-as-is "$BASE_URL/./norm/./sub/file.txt")" "norm"
+check "编码的点号分量被去掉" "$(curl -s "$BASE_URL/norm/%2E/sub/file.txt")" "norm"
+check "结尾的点号分量指向目录本身" "$(status '/norm/sub/.?format=json' --path-as-is)" "200"
+check "目录内的..也被拒绝" "$(status /norm/sub/..%2Fsub/file.txt)" "403"
+check "不存在的路径中的..返回403而不是404" "$(status /missing/..%2F..%2Fetc/passwd)" "403"
+check "编码的点号上级目录被拒绝" "$(status /norm/%2E%2E/plain.txt)" "403"
+check "规整后仍按目录列出" \
+    "$(curl -s --path-as-is "$BASE_URL//norm/./sub/?format=json" | grep -o '"name":"file.txt"')" \
+    '"name":"file.txt"'
+stop_server
+rm -rf "$ROOT/norm"
+
+# ---------------------------------------------------------------
+# CORS：默认只允许同源，--cors-origin 指定允许的来源
+# ---------------------------------------------------------------
+preflight() {
+    curl -s -o /dev/null -D - -X OPTIONS -H "Origin: $1" -H "Access-Control-Request-Method: $2" "$BASE_URL/plain.txt" \
+        | grep -i "^$3:" | cut -d' ' -f2- | tr -d '\r'
+}
+
+start_server "$ROOT"
+check "默认不带CORS头" "$(header /plain.txt access-control-allow-origin -H 'Origin: https://a.example')" ""
+stop_server
+
+start_server --cors-origin https://a.example --cors-origin http://b.example:8080 "$ROOT"
+check "允许列表中的来源" \
+    "$(header /plain.txt access-control-allow-origin -H 'Origin: https://a.example')" "https://a.example"
+check "允许带端口的来源" \
+    "$(header /plain.txt access-control-allow-origin -H 'Origin: http://b.example:8080')" "http://b.example:8080"
+check "不在列表中的来源没有CORS头" \
+    "$(header /plain.txt access-control-allow-origin -H 'Origin: https://evil.example')" ""
+check "预检只允许只读方法" "$(preflight https://a.example GET access-control-allow-methods)" "GET,HEAD"
+check "暴露Content-Range" \
+    "$(header /plain.txt access-control-expose-headers -H 'Origin: https://a.example' | grep -o -i 'content-range')" "content-range"
+stop_server
+
+start_server --cors-origin '*' --read-only false "$ROOT"
+check "*允许任意来源" "$(header /plain.txt access-control-allow-origin -H 'Origin: https://c.example')" "*"
+check "可写时预检包含写操作" \
+    "$(preflight https://c.example DELETE access-control-allow-methods)" "GET,HEAD,PUT,POST,DELETE,PATCH"
+stop_server
+
+timeout 5 "$BINARY_PATH" -p $SERVER_PORT --cors-origin 'https://a.example/path' "$ROOT" > "$WORK_DIR/startup.log" 2>&1
+check "带路径的来源被拒绝" "$?" "2"
+
+# ---------------------------------------------------------------
+# 目录补全末尾斜杠，文件去掉末尾斜杠
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/slash dir/sub"
+echo 'slash' > "$ROOT/slash dir/a.txt"
+
+start_server "$ROOT"
+
+check "目录缺少斜杠时返回308" "$(status '/slash%20dir')" "308"
+check "重定向到带斜杠的目录" "$(header '/slash%20dir' location)" "/slash%20dir/"
+check "子目录重定向" "$(header '/slash%20dir/sub' location)" "/slash%20dir/sub/"
+check "文件带斜杠时返回308" "$(status '/slash%20dir/a.txt/')" "308"
+check "重定向时保留查询参数" "$(header '/slash%20dir/a.txt/?download=1' location)" \
+    "/slash%20dir/a%2Etxt?download=1"
+check "根目录不重定向" "$(status /)" "200"
+check "JSON请求不重定向" "$(status '/slash%20dir?format=json')" "200"
+check "打包下载不重定向" "$(status '/slash%20dir?download=zip')" "200"
+check "带斜杠的目录返回200" "$(status '/slash%20dir/')" "200"
+check "列表中目录链接以斜杠结尾" \
+    "$(curl -s "$BASE_URL/slash%20dir/?format=json" | grep -o '"url":"[^"]*sub[^"]*"')" \
+    '"url":"/slash%20dir%2Fsub/"'
+
+stop_server
+
+start_server --base-path /files "$ROOT"
+check "重定向包含前缀" "$(header '/files/slash%20dir' location)" "/files/slash%20dir/"
+stop_server
+rm -r "$ROOT/slash dir"
+
+# ---------------------------------------------------------------
+# Vary: 只有真正按请求头协商过的响应才带上
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/vary-dir"
+head -c 4000 /dev/zero | tr '\0' 'v' > "$ROOT/vary.txt"
+gzip -k "$ROOT/vary.txt"
+printf '\x00\x01\x02' > "$ROOT/vary.bin"
+
+# vary 路径 [curl参数...]，多个Vary头合在一起输出
+vary() {
+    curl -s -g -D - -o /dev/null "${@:2}" "$BASE_URL$1" | grep -i '^vary:' | cut -d' ' -f2- | tr -d '\r' | paste -sd '|' -
+}
+
+start_server "$ROOT"
+check "目录页面按Accept和Accept-Encoding协商" "$(vary /vary-dir/)" "accept, accept-encoding"
+check "Accept选择JSON时同样带Vary" "$(vary /vary-dir/ -H 'Accept: application/json')" "accept, accept-encoding"
+check "?format=json不再看Accept" "$(vary '/vary-dir/?format=json')" "accept-encoding"
+check "目录重定向也取决于Accept" "$(vary /vary-dir)" "accept"
+check "可压缩的文件只按Accept-Encoding" "$(vary /vary.txt)" "accept-encoding"
+check "有预压缩文件时Accept-Encoding只出现一次" "$(vary /vary.txt -H 'Accept-Encoding: identity')" "accept-encoding"
+check "发送预压缩文件时带Vary" "$(vary /vary.txt -H 'Accept-Encoding: gzip')" "accept-encoding"
+check "不可压缩的文件不带Vary" "$(vary /vary.bin)" ""
+check "统计页面按Accept协商" "$(vary /_sfs/stats)" "accept, accept-encoding"
+check "统计JSON不看Accept" "$(vary '/_sfs/stats?format=json')" "accept-encoding"
+stop_server
+rm -r "$ROOT/vary-dir" "$ROOT/vary.txt" "$ROOT/vary.txt.gz" "$ROOT/vary.bin"
+
+# ---------------------------------------------------------------
+# 目录页面的无障碍标记和键盘导航
+# ---------------------------------------------------------------
+start_server "$ROOT"
+PAGE=$(curl -s "$BASE_URL/")
+check "文件列表带list语义" "$(echo "$PAGE" | grep -c 'id="fileList" role="list"')" "1"
+check "每个条目是listitem" "$(echo "$PAGE" | grep -c 'role="listitem"')" "2"
+check "面包屑是导航区域" "$(echo "$PAGE" | grep -c '<nav class="breadcrumb"')" "1"
+check "操作按钮带aria-label" "$(echo "$PAGE" | grep -c '<button class="download-btn" [^>]*aria-label=')" "7"
+check "按钮不嵌套在链接里" "$(echo "$PAGE" | grep -c '<a href="${entry.url}" class="file-link"')" "1"
+check "注册了方向键导航" "$(echo "$PAGE" | grep -c "addEventListener('keydown', navigateList)")" "1"
+check "键盘焦点样式" "$(echo "$PAGE" | grep -c 'a:focus-visible, button:focus-visible')" "1"
+check "提示消息会被读屏软件朗读" "$(echo "$PAGE" | grep -c 'id="toast" role="status" aria-live="polite"')" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 内置图标字体，默认不请求Google Fonts
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/static"
+echo 'own' > "$ROOT/static/own.txt"
+
+start_server "$ROOT"
+check "页面不引用Google Fonts" "$(curl -s "$BASE_URL/" | grep -c 'googleapis')" "0"
+check "预览页面也不引用" "$(curl -s "$BASE_URL/plain.txt?view=1" | grep -c 'googleapis')" "0"
+check "页面引用内置字体" "$(curl -s "$BASE_URL/" | grep -c "url(/static/material-icons.ttf)")" "1"
+check "内置字体返回200" "$(status /static/material-icons.ttf)" "200"
+check "内置字体的Content-Type" "$(header /static/material-icons.ttf content-type)" "font/ttf"
+check "内置字体是TrueType" "$(curl -s "$BASE_URL/static/material-icons.ttf" | head -c 4 | od -An -tx1 | tr -d ' ')" "00010000"
+check "/static/下的其他文件照常提供" "$(curl -s "$BASE_URL/static/own.txt")" "own"
+stop_server
+
+start_server --base-path /files "$ROOT"
+check "内置字体地址带前缀" "$(curl -s "$BASE_URL/files/" | grep -c "url(/files/static/material-icons.ttf)")" "1"
+check "前缀下的内置字体返回200" "$(status /files/static/material-icons.ttf)" "200"
+stop_server
+
+start_server --cdn "$ROOT"
+check "--cdn时从Google Fonts加载" "$(curl -s "$BASE_URL/" | grep -c 'fonts.googleapis.com')" "2"
+check "--cdn时不提供内置字体" "$(status /static/material-icons.ttf)" "404"
+stop_server
+rm -r "$ROOT/static"
+
+# ---------------------------------------------------------------
+# TCP连接选项
+# ---------------------------------------------------------------
+start_server --keepalive 30 --tcp-nodelay off "$ROOT"
+check "设置keepalive后正常响应" "$(curl -s "$BASE_URL/plain.txt")" "plain"
+stop_server
+
+start_server --tcp-nodelay on "$ROOT"
+check "连接复用时依次返回小文件" \
+    "$(curl -s "$BASE_URL/plain.txt" "$BASE_URL/digits.txt" "$BASE_URL/plain.txt" | tr -d '\n')" "plain0123456789plain"
+stop_server
+
+timeout 5 "$BINARY_PATH" --unix "$WORK_DIR/tcp.sock" --keepalive 30 "$ROOT" > /dev/null 2>&1
+check "Unix socket不能设置keepalive" "$?" "2"
+check "无效的开关值被拒绝" "$(timeout 5 "$BINARY_PATH" --tcp-nodelay maybe "$ROOT" > /dev/null 2>&1; echo $?)" "2"
+
+# ---------------------------------------------------------------
+# 自定义页面标题
+# ---------------------------------------------------------------
+start_server "$ROOT"
+check "默认标题" "$(curl -s "$BASE_URL/" | grep -c "<h1>Swizzer's Sharing Service</h1>")" "1"
+check "默认没有副标题" "$(curl -s "$BASE_URL/" | grep -c 'class="subtitle"')" "0"
+stop_server
+
+start_server --title 'Team <Files>' --subtitle '内部资料 & 发布包' "$ROOT"
+check "自定义标题显示在页面顶部" "$(curl -s "$BASE_URL/" | grep -c '<h1>Team &lt;Files&gt;</h1>')" "1"
+check "自定义标题用于标签页" "$(curl -s "$BASE_URL/" | grep -c '<title>Team &lt;Files&gt; - /</title>')" "1"
+check "副标题显示在标题下方" \
+    "$(curl -s "$BASE_URL/" | grep -c '<p class="subtitle">内部资料 &amp; 发布包</p>')" "1"
+check "预览页面也使用自定义标题" \
+    "$(curl -s "$BASE_URL/plain.txt?view=1" | grep -c '<title>Team &lt;Files&gt; - ')" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 没有扩展名的文件按内容识别类型
+# ---------------------------------------------------------------
+printf '\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0' > "$ROOT/picture"
+{ printf '%%PDF-1.4\n'; head -c 200000 /dev/zero; } > "$ROOT/document"
+printf 'just text' > "$ROOT/notes"
+
+start_server "$ROOT"
+check "默认不识别内容" "$(header /picture content-type)" "application/octet-stream"
+stop_server
+
+start_server --sniff-mime --cache-max-file-size 100K "$ROOT"
+check "识别出PNG图片" "$(header /picture content-type)" "image/png"
+check "识别出的图片inline显示" "$(header /picture content-disposition | cut -d';' -f1)" "inline"
+check "命中缓存时仍是识别出的类型" "$(header /picture content-type)" "image/png"
+check "HEAD请求同样识别" "$(header /picture content-type -I)" "image/png"
+check "流式传输的大文件也识别" "$(header /document content-type)" "application/pdf"
+check "?stat=1的mime也是识别出的类型" \
+    "$(curl -s "$BASE_URL/picture?stat=1" | grep -o '"mime":"[^"]*"')" '"mime":"image/png"'
+check "识别不出时仍是octet-stream" "$(header /notes content-type)" "application/octet-stream"
+check "有扩展名的文件不识别" "$(header /plain.txt content-type)" "text/plain"
+stop_server
+rm "$ROOT/picture" "$ROOT/document" "$ROOT/notes"
+
+# ---------------------------------------------------------------
+# 禁止目录列表
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/site/docs" "$ROOT/site/assets"
+echo '<h1>home</h1>' > "$ROOT/site/index.html"
+echo '<h1>docs</h1>' > "$ROOT/site/docs/index.html"
+echo 'body{}' > "$ROOT/site/assets/style.css"
+
+start_server --no-directory-listing "$ROOT/site"
+check "有index文件的根目录照常返回" "$(curl -s "$BASE_URL/")" "<h1>home</h1>"
+check "有index文件的子目录照常返回" "$(curl -s "$BASE_URL/docs/")" "<h1>docs</h1>"
+check "没有index文件的目录返回403" "$(status /assets/)" "403"
+check "禁止列表时JSON也返回403" "$(status '/assets/?format=json')" "403"
+check "禁止列表时打包下载返回403" "$(status '/assets?download=zip')" "403"
+check "禁止列表时搜索返回403" "$(status '/_sfs/search?q=style')" "403"
+check "目录中的文件仍可下载" "$(curl -s "$BASE_URL/assets/style.css")" "body{}"
+stop_server
+
+timeout 5 "$BINARY_PATH" -p $SERVER_PORT --no-directory-listing --no-index "$ROOT" > /dev/null 2>&1
+check "--no-directory-listing与--no-index冲突" "$?" "2"
+rm -r "$ROOT/site"
+
+# ---------------------------------------------------------------
+# X-RateLimit: 告诉客户端实际生效的限速
+# ---------------------------------------------------------------
+start_server --rate-limit 1M --no-cache "$ROOT"
+check "单个下载限速" "$(header /plain.txt x-ratelimit -r 0-0)" "1048576"
+check "HEAD与GET一样带限速" "$(header /plain.txt x-ratelimit -I)" "1048576"
+check "错误响应不带限速" "$(header /missing.txt x-ratelimit)" ""
+stop_server
+
+start_server --rate-limit 1M "$ROOT"
+check "还没缓存时no-store的HEAD带限速" "$(header /plain.txt x-ratelimit -I -H 'Cache-Control: no-store')" "1048576"
+check "HEAD可缓存的小文件不带限速" "$(header /plain.txt x-ratelimit -I)" ""
+check "缓存的小文件不限速时不带" "$(header /plain.txt x-ratelimit)" ""
+stop_server
+
+start_server --rate-limit 0 --no-cache "$ROOT"
+check "不限速时不带" "$(header /plain.txt x-ratelimit)" ""
+stop_server
+
+start_server --rate-limit 1M --tiered-rate 1:512K --no-cache "$ROOT"
+check "分档限速" "$(header /plain.txt x-ratelimit -r 0-0)" "524288"
+stop_server
+
+start_server --rate-limit 1M --rate-limit-per-ip "$ROOT"
+check "按IP限速时缓存的小文件也带" "$(header /plain.txt x-ratelimit)" "1048576"
+stop_server
+
+start_server --rate-limit 0 --global-rate 2M --tiered-rate 1:1M --no-cache "$ROOT"
+check "多个限速取最小值" "$(header /plain.txt x-ratelimit -r 0-0)" "1048576"
+check "全局限速" "$(header / x-ratelimit)" "2097152"
+stop_server
+
+# ---------------------------------------------------------------
+# 请求ID
+# ---------------------------------------------------------------
+start_server --log-format json "$ROOT"
+ID=$(header /plain.txt x-request-id)
+check "响应带生成的X-Request-Id" "$(echo "$ID" | grep -cE '^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[0-9a-f]{4}-[0-9a-f]{12}$')" "1"
+check "每个请求的ID不同" "$([ "$ID" != "$(header /plain.txt x-request-id)" ] && echo yes)" "yes"
+check "错误响应也带ID" "$(header /missing.txt x-request-id | wc -c)" "37"
+check "未信任代理时不沿用客户端的ID" "$(header /plain.txt x-request-id -H 'X-Request-Id: abc-123' | wc -c)" "37"
+sleep 0.2
+check "日志中带请求ID" "$(grep -c "\"request_id\":\"$ID\"" "$WORK_DIR/server.log")" "1"
+stop_server
+
+start_server --trust-proxy 127.0.0.1 "$ROOT"
+check "信任代理时沿用X-Request-Id" "$(header /plain.txt x-request-id -H 'X-Request-Id: abc-123')" "abc-123"
+check "过长的ID重新生成" \
+    "$(header /plain.txt x-request-id -H "X-Request-Id: $(printf 'a%.0s' {1..200})" | wc -c)" "37"
+sleep 0.2
+check "文本日志末尾是请求ID" "$(grep -c 'abc-123' "$WORK_DIR/server.log")" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 读取大目录时不阻塞其他请求
+# ---------------------------------------------------------------
+mkdir "$ROOT/big-dir"
+(cd "$ROOT/big-dir" && seq 1 50000 | xargs touch)
+
+# 列出大目录的同时请求小文件，分别记录两者的耗时
+start_server "$ROOT"
+curl -s -o /dev/null -w "%{time_total}" "$BASE_URL/big-dir/" > "$WORK_DIR/listing.time" &
+sleep 0.05
+curl -s -o /dev/null -w "%{time_total}" "$BASE_URL/plain.txt" > "$WORK_DIR/small.time"
+wait $!
+# 被阻塞时小文件要等目录读完，耗时只比列表少开头的50ms
+check "读取大目录时小文件请求不被阻塞" \
+    "$(awk -v small="$(cat "$WORK_DIR/small.time")" -v listing="$(cat "$WORK_DIR/listing.time")" \
+        'BEGIN { if (small * 4 < listing) print "yes" }')" "yes"
+stop_server
+rm -r "$ROOT/big-dir"
+
+# ---------------------------------------------------------------
+# 内置接口在 /_sfs/ 下，不遮住同名的目录
+# ---------------------------------------------------------------
+for name in stats search healthz metrics; do
+    mkdir "$ROOT/$name"
+    echo "$name" > "$ROOT/$name/a.txt"
+done
+
+start_server "$ROOT"
+for name in stats search healthz metrics; do
+    check "名为$name的目录可以列出" "$(status /$name/)" "200"
+    check "名为$name的目录中的文件可以下载" "$(curl -s "$BASE_URL/$name/a.txt")" "$name"
+done
+check "内置接口在保留前缀下" "$(status /_sfs/healthz)" "200"
+stop_server
+rm -r "$ROOT/stats" "$ROOT/search" "$ROOT/healthz" "$ROOT/metrics"
+timeout 5 "$BINARY_PATH" -p $SERVER_PORT --mount "/_sfs=$ROOT" "$ROOT" > /dev/null 2>&1
+check "不能挂载到保留前缀" "$?" "2"
+
+# ---------------------------------------------------------------
+# 目录页面内联脚本中的JSON转义 <，文件名不能提前结束script标签
+# ---------------------------------------------------------------
+mkdir -p "$ROOT/xss/x</script><script>alert(1)<"
+: > "$ROOT/xss/x</script><script>alert(1)</script>"
+ln -s "x</script><script>alert(1)</script>" "$ROOT/xss/link"
+
+start_server --read-only false --follow-symlinks "$ROOT"
+check "上传带<的文件名" \
+    "$(curl -s -F "file=@$WORK_DIR/form.txt;filename=<!--<script>.txt" "$BASE_URL/xss/")" \
+    '[{"name":"<!--<script>.txt","status":201}]'
+XSS_PAGE=$(curl -s "$BASE_URL/xss/")
+check "文件名中的<!--<script>被转义" "$(echo "$XSS_PAGE" | grep -c '<!--<script>')" "0"
+check "链接目标中的</script>被转义" "$(echo "$XSS_PAGE" | grep -c '</script><script>alert')" "0"
+check "子目录的标题和面包屑同样转义" \
+    "$(curl -s "$BASE_URL/xss/x%3C/script%3E%3Cscript%3Ealert(1)%3C/" | grep -c '<script>alert')" "0"
+stop_server
+rm -r "$ROOT/xss"
+
+# ---------------------------------------------------------------
+# 配置文件中的值与命令行参数受同样的约束
+# ---------------------------------------------------------------
+# config_rejected 配置内容 [参数...]，配置被拒绝时输出yes
+config_rejected() {
+    printf '%s\n' "$1" > "$WORK_DIR/conflict.toml"
+    timeout 5 "$BINARY_PATH" --config "$WORK_DIR/conflict.toml" "${@:2}" "$ROOT" > /dev/null 2>&1
+    [ $? -eq 1 ] && echo yes
+}
+
+check "配置文件中dual_stack与bind冲突" "$(config_rejected $'dual_stack = true\nbind = "127.0.0.1"')" "yes"
+check "配置文件中dual_stack与命令行的--bind冲突" "$(config_rejected 'dual_stack = true' -b 127.0.0.1)" "yes"
+check "配置文件中unix与port冲突" "$(config_rejected $'unix = "/tmp/x.sock"\nport = 9000')" "yes"
+check "配置文件中unix与keepalive冲突" "$(config_rejected $'unix = "/tmp/x.sock"\nkeepalive = 30')" "yes"
+check "配置文件中unix与命令行的-p冲突" "$(config_rejected 'unix = "/tmp/x.sock"' -p 9000)" "yes"
+check "配置文件中no_directory_listing与no_index冲突" \
+    "$(config_rejected $'no_directory_listing = true\nno_index = true')" "yes"
+check "配置文件中cache_after不能为0" "$(config_rejected 'cache_after = 0')" "yes"
+printf 'title = "Config"\ncache_after = 2\n' > "$WORK_DIR/valid.toml"
+start_server --config "$WORK_DIR/valid.toml" "$ROOT"
+check "没有冲突的配置文件正常启动" "$(curl -s "$BASE_URL/" | grep -c '<title>Config')" "1"
+stop_server
+
+# ---------------------------------------------------------------
+# 空文件
+# ---------------------------------------------------------------
+: > "$ROOT/empty.txt"
+
+start_server "$ROOT"
+check "空文件返回200" "$(status /empty.txt)" "200"
+check "空文件Content-Length为0" "$(header /empty.txt content-length)" "0"
+check "空文件响应体为空" "$(curl -s "$BASE_URL/empty.txt" | wc -c)" "0"
+check "空文件也能作为附件下载" "$(header '/empty.txt?download=1' content-disposition | cut -d';' -f1)" "attachment"
+check "空文件HEAD请求Content-Length为0" "$(header /empty.txt content-length -I)" "0"
+check "空文件不走流式传输" "$(grep -c 'Streaming file: .*empty.txt' "$WORK_DIR/server.log")" "0"
+stop_server
+rm "$ROOT/empty.txt"
+
+# ---------------------------------------------------------------
+# Server响应头
+# ---------------------------------------------------------------
+VERSION=$(grep -m1 '^version' "$PROJECT_DIR/Cargo.toml" | cut -d'"' -f2)
+
+start_server "$ROOT"
+check "响应带Server头" "$(header /plain.txt server)" "http-file-server/$VERSION"
+check "404也带Server头" "$(header /missing.txt server)" "http-file-server/$VERSION"
+stop_server
+
+start_server --server-header off "$ROOT"
+check "--server-header off时不发送" "$(header /plain.txt server)" ""
+stop_server
+
+# ---------------------------------------------------------------
+# 下载统计
+# ---------------------------------------------------------------
+head -c 5000000 /dev/zero > "$ROOT/big.bin"
+
+start_server --stats-file "$WORK_DIR/stats.json" "$ROOT"
+curl -s -o /dev/null "$BASE_URL/plain.txt"
+curl -s -o /dev/null "$BASE_URL/plain.txt"
+curl -s -o /dev/null "$BASE_URL/big.bin"
+curl -s -o /dev/null -H 'Range: bytes=0-9' "$BASE_URL/plain.txt"
+curl -s -o /dev/null -H 'Range: bytes=0-9' "$BASE_URL/big.bin"
+curl -s -o /dev/null -I "$BASE_URL/big.bin"
+curl -s -o /dev/null "$BASE_URL/plain-dir"
+check "只统计完整传输" "$(curl -s "$BASE_URL/_sfs/stats?format=json")" \
+    '[{"path":"/plain.txt","downloads":2},{"path":"/big.bin","downloads":1}]'
+check "统计页面返回HTML" "$(header /_sfs/stats content-type)" "text/html; charset=utf-8"
+STATS_PAGE=$(curl -s "$BASE_URL/_sfs/stats")
+check "统计页面使用统一的页面框架" "$(echo "$STATS_PAGE" | grep -c 'id="themeToggle"')" "1"
+check "统计页面是中文的" "$(echo "$STATS_PAGE" | grep -o '<th>下载次数</th>')" "<th>下载次数</th>"
+check "统计页面没有下载按钮" "$(echo "$STATS_PAGE" | grep -c 'download=1')" "0"
+stop_server
+
+start_server --stats-file "$WORK_DIR/stats.json" "$ROOT"
+check "重启后从文件恢复统计" "$(curl -s "$BASE_URL/_sfs/stats?format=json")" \
+    '[{"path":"/plain.txt","downloads":2},{"path":"/big.bin","downloads":1}]'
+stop_server
+rm "$ROOT/big.bin"
+
+# ---------------------------------------------------------------
+# 请求超时只限制首字节时间，不中断正在进行的传输
+# ---------------------------------------------------------------
+head -c 5000000 /dev/zero > "$ROOT/slow.bin"
+
+start_server --request-timeout 1 --rate-limit 2M "$ROOT"
+check "超过超时时间的下载仍然完整" \
+    "$(curl -s -o /dev/null -w "%{http_code} %{size_download}" "$BASE_URL/slow.bin")" "200 5000000"
+stop_server
+rm "$ROOT/slow.bin"
+
+# ---------------------------------------------------------------
+# IPv6和双栈监听
+# ---------------------------------------------------------------
+"$BINARY_PATH" -p $SERVER_PORT -b '[::1]' "$ROOT" > "$WORK_DIR/server.log" 2>&1 &
+SERVER_PID=$!
+sleep 1
+check "带方括号的IPv6地址" \
+    "$(curl -s -o /dev/null -w "%{http_code}" "http://[::1]:$SERVER_PORT/plain.txt")" "200"
+check "启动信息中的IPv6地址带方括号" \
+    "$(grep -c "http://\[::1\]:$SERVER_PORT" "$WORK_DIR/server.log")" "2"
+stop_server
+
+"$BINARY_PATH" -p $SERVER_PORT --dual-stack "$ROOT" > "$WORK_DIR/server.log" 2>&1 &
+SERVER_PID=$!
+sleep 1
+check "双栈接受IPv4客户端" "$(status /plain.txt)" "200"
+check "双栈接受IPv6客户端" \
+    "$(curl -s -o /dev/null -w "%{http_code}" "http://[::1]:$SERVER_PORT/plain.txt")" "200"
+stop_server
+
+# ---------------------------------------------------------------
+# 监听Unix domain socket
+# ---------------------------------------------------------------
+SOCKET="$WORK_DIR/server.sock"
+# 模拟上次异常退出留下的socket文件
+python3 -c "import socket,sys; socket.socket(socket.AF_UNIX).bind(sys.argv[1])" "$SOCKET"
+
+"$BINARY_PATH" --unix "$SOCKET" "$ROOT" > "$WORK_DIR/server.log" 2>&1 &
+SERVER_PID=$!
+sleep 1
+check "通过Unix socket下载文件" "$(curl -s --unix-socket "$SOCKET" http://localhost/plain.txt)" "plain"
+check "通过Unix socket列出目录" \
+    "$(curl -s -o /dev/null -w "%{http_code}" --unix-socket "$SOCKET" http://localhost/)" "200"
+stop_server
+check "退出后删除socket文件" "$([ -e "$SOCKET" ] && echo exists)" ""
+
+# ---------------------------------------------------------------
+if [ $FAILED -ne 0 ]; then
+    echo "❌ 冒烟测试失败"
+    exit 1
+fi
+echo "✅ 冒烟测试全部通过"